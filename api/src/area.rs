@@ -0,0 +1,87 @@
+use chrono_tz::Europe;
+use chrono_tz::Tz;
+
+/// Bidding zone with its EIC code and the local time zone the market operates in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub code: &'static str,
+    pub eic: &'static str,
+    pub timezone: Tz,
+}
+
+pub const AREAS: &[Area] = &[
+    Area { code: "FI", eic: "10YFI-1--------U", timezone: Europe::Helsinki },
+    Area { code: "SE1", eic: "10Y1001A1001A44P", timezone: Europe::Stockholm },
+    Area { code: "SE2", eic: "10Y1001A1001A45N", timezone: Europe::Stockholm },
+    Area { code: "SE3", eic: "10Y1001A1001A46L", timezone: Europe::Stockholm },
+    Area { code: "SE4", eic: "10Y1001A1001A47J", timezone: Europe::Stockholm },
+    Area { code: "NO1", eic: "10YNO-1--------2", timezone: Europe::Oslo },
+    Area { code: "NO2", eic: "10YNO-2--------T", timezone: Europe::Oslo },
+    Area { code: "NO3", eic: "10YNO-3--------J", timezone: Europe::Oslo },
+    Area { code: "NO4", eic: "10YNO-4--------9", timezone: Europe::Oslo },
+    Area { code: "NO5", eic: "10Y1001A1001A48H", timezone: Europe::Oslo },
+    Area { code: "DK1", eic: "10YDK-1--------W", timezone: Europe::Copenhagen },
+    Area { code: "DK2", eic: "10YDK-2--------M", timezone: Europe::Copenhagen },
+    Area { code: "EE", eic: "10Y1001A1001A39I", timezone: Europe::Tallinn },
+    Area { code: "LV", eic: "10YLV-1001A00074", timezone: Europe::Riga },
+    Area { code: "LT", eic: "10YLT-1001A0008Q", timezone: Europe::Vilnius },
+    Area { code: "DE_LU", eic: "10Y1001A1001A82H", timezone: Europe::Berlin },
+    Area { code: "NL", eic: "10YNL----------L", timezone: Europe::Amsterdam },
+    Area { code: "BE", eic: "10YBE----------2", timezone: Europe::Brussels },
+    Area { code: "FR", eic: "10YFR-RTE------C", timezone: Europe::Paris },
+    Area { code: "AT", eic: "10YAT-APG------L", timezone: Europe::Vienna },
+    Area { code: "CH", eic: "10YCH-SWISSGRIDZ", timezone: Europe::Zurich },
+    Area { code: "PL", eic: "10YPL-AREA-----S", timezone: Europe::Warsaw },
+    Area { code: "CZ", eic: "10YCZ-CEPS-----N", timezone: Europe::Prague },
+    Area { code: "SK", eic: "10YSK-SEPS-----K", timezone: Europe::Bratislava },
+    Area { code: "HU", eic: "10YHU-MAVIR----U", timezone: Europe::Budapest },
+    Area { code: "SI", eic: "10YSI-ELES-----O", timezone: Europe::Ljubljana },
+    Area { code: "HR", eic: "10YHR-HEP------M", timezone: Europe::Zagreb },
+    Area { code: "RO", eic: "10YRO-TEL------P", timezone: Europe::Bucharest },
+    Area { code: "BG", eic: "10YCA-BULGARIA-R", timezone: Europe::Sofia },
+    Area { code: "GR", eic: "10YGR-HTSO-----Y", timezone: Europe::Athens },
+    Area { code: "ES", eic: "10YES-REE------0", timezone: Europe::Madrid },
+    Area { code: "PT", eic: "10YPT-REN------W", timezone: Europe::Lisbon },
+];
+
+impl Area {
+    /// Looks the area up by its short code (`FI`, `SE3`, ...) or its EIC code.
+    pub fn find(code_or_eic: &str) -> Option<Area> {
+        AREAS
+            .iter()
+            .find(|area| area.code.eq_ignore_ascii_case(code_or_eic) || area.eic == code_or_eic)
+            .copied()
+    }
+
+    /// ISO 3166 country code of the area, e.g. `SE` for `SE3`.
+    pub fn country(&self) -> &'static str {
+        &self.code[..2]
+    }
+}
+
+/// Whether the value has the form of an EIC code: 16 characters of upper case letters, digits and dashes.
+pub fn is_eic_code(value: &str) -> bool {
+    value.len() == 16 && value.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_area() {
+        assert_eq!(Area::find("fi").unwrap().eic, "10YFI-1--------U");
+        assert_eq!(Area::find("10Y1001A1001A46L").unwrap().code, "SE3");
+        assert_eq!(Area::find("EE").unwrap().timezone, Europe::Tallinn);
+        assert!(Area::find("XX").is_none());
+    }
+
+    #[test]
+    fn test_is_eic_code() {
+        assert!(AREAS.iter().all(|area| is_eic_code(area.eic)));
+        assert!(!is_eic_code("FI"));
+        assert!(!is_eic_code("10yfi-1--------u"));
+        assert!(!is_eic_code("10YFI-1-------' "));
+        assert!(!is_eic_code("10YFI-1--------U' OR ''='"));
+    }
+}
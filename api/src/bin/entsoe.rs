@@ -0,0 +1,247 @@
+use std::cmp;
+
+use api::{expand_period, Area, EntsoeDocument, Query};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde_json::json;
+
+/// Ad-hoc data pulls from the ENTSO-E Transparency Platform API
+#[derive(Parser)]
+#[command(name = "entsoe", version, about)]
+struct Cli {
+    /// ENTSO-E API token
+    #[arg(long, env = "SECURITY_TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Day-ahead prices
+    Prices(QueryArgs),
+    /// Actual total load
+    Load(QueryArgs),
+    /// Actual generation per production type
+    Generation(QueryArgs),
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// Bidding zone code (FI, SE3, ...) or EIC code
+    #[arg(long)]
+    zone: String,
+    /// Start date in the zone's local time, YYYY-MM-DD or YYYY-MM-DDTHH:MM
+    #[arg(long)]
+    from: String,
+    /// End date (exclusive) in the zone's local time, YYYY-MM-DD or YYYY-MM-DDTHH:MM
+    #[arg(long)]
+    to: String,
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+    Table,
+}
+
+struct Row {
+    time: DateTime<Tz>,
+    series: Option<String>,
+    value: f64,
+    unit: String,
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+
+    let cli = Cli::parse();
+    if let Err(err) = run(cli).await {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), anyhow::Error> {
+    let token = cli
+        .token
+        .ok_or_else(|| anyhow::anyhow!("Missing API token, use --token or SECURITY_TOKEN"))?;
+
+    let (args, rows) = match &cli.command {
+        Command::Prices(args) => (args, fetch(&token, args, |eic| Query::day_ahead_prices(eic, eic)).await?),
+        Command::Load(args) => (args, fetch(&token, args, Query::actual_total_load).await?),
+        Command::Generation(args) => (args, fetch(&token, args, Query::actual_generation_per_type).await?),
+    };
+
+    let value_header = match cli.command {
+        Command::Prices(_) => "price",
+        _ => "quantity",
+    };
+    print_rows(&rows, value_header, args.format)
+}
+
+async fn fetch<F>(token: &str, args: &QueryArgs, query: F) -> Result<Vec<Row>, anyhow::Error>
+where
+    F: Fn(&str) -> Query,
+{
+    let area = Area::find(&args.zone).ok_or_else(|| anyhow::anyhow!("Unknown zone {}", args.zone))?;
+    let start = parse_local_time(&args.from, &area.timezone)?;
+    let end = parse_local_time(&args.to, &area.timezone)?;
+    if start >= end {
+        return Err(anyhow::anyhow!("--from must be before --to"));
+    }
+
+    // The API only serves up to one year per request
+    let max_duration = Duration::days(365);
+    let mut rows = Vec::new();
+    let mut current_start = start;
+    while current_start < end {
+        let current_end = cmp::min(current_start + max_duration, end);
+        let document = query(area.eic)
+            .time_interval(current_start, current_end)
+            .send(token)
+            .await?;
+        rows.extend(document_rows(document, &area.timezone)?);
+        current_start = current_end;
+    }
+
+    rows.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.series.cmp(&b.series)));
+    Ok(rows)
+}
+
+fn parse_local_time(value: &str, timezone: &Tz) -> Result<DateTime<Utc>, anyhow::Error> {
+    let naive = match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date.and_hms(0, 0, 0),
+        Err(_) => NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+            .map_err(|_| anyhow::anyhow!("Invalid date {}, expected YYYY-MM-DD or YYYY-MM-DDTHH:MM", value))?,
+    };
+
+    timezone
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist in {}", value, timezone.name()))
+}
+
+fn document_rows(document: EntsoeDocument, timezone: &Tz) -> Result<Vec<Row>, anyhow::Error> {
+    let mut rows = Vec::new();
+
+    match document {
+        EntsoeDocument::Publication(document) => {
+            for time_serie in document.time_series.iter() {
+                let unit = format!("{}/{}", time_serie.currency_unit, time_serie.price_measure_unit);
+                for period in time_serie.period.iter() {
+                    let points: Vec<(i32, f64)> = period.point.iter().map(|p| (p.position, p.price as f64)).collect();
+                    for (time, value) in expand_period(&period.time_interval, &period.resolution, &points)? {
+                        rows.push(Row { time: time.with_timezone(timezone), series: None, value, unit: unit.clone() });
+                    }
+                }
+            }
+        }
+        EntsoeDocument::GL(document) => {
+            for time_serie in document.time_series.iter() {
+                let series = time_serie.mkt_psr_type.as_ref().map(|psr| psr.psr_type.clone());
+                for period in time_serie.period.iter() {
+                    let points: Vec<(i32, f64)> = period.point.iter().map(|p| (p.position, p.quantity)).collect();
+                    for (time, value) in expand_period(&period.time_interval, &period.resolution, &points)? {
+                        rows.push(Row {
+                            time: time.with_timezone(timezone),
+                            series: series.clone(),
+                            value,
+                            unit: time_serie.quantity_measure_unit.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        EntsoeDocument::Acknowledgement(document) => {
+            let reasons: Vec<String> = document
+                .reason
+                .iter()
+                .map(|reason| format!("{} {}", reason.code, reason.text.as_deref().unwrap_or("")))
+                .collect();
+            // No data for the interval is not an error for a pull spanning several requests
+            if document.reason.iter().all(|reason| reason.code == "999") {
+                eprintln!("{}", reasons.join(", "));
+            } else {
+                return Err(anyhow::anyhow!(reasons.join(", ")));
+            }
+        }
+        other => return Err(anyhow::anyhow!("Unexpected {} in the response", other.root_element())),
+    }
+
+    Ok(rows)
+}
+
+fn print_rows(rows: &[Row], value_header: &str, format: Format) -> Result<(), anyhow::Error> {
+    let with_series = rows.iter().any(|row| row.series.is_some());
+
+    match format {
+        Format::Csv => {
+            if with_series {
+                println!("time,type,{},unit", value_header);
+            } else {
+                println!("time,{},unit", value_header);
+            }
+            for row in rows {
+                match &row.series {
+                    Some(series) => println!("{},{},{},{}", row.time.to_rfc3339(), series, row.value, row.unit),
+                    None => println!("{},{},{}", row.time.to_rfc3339(), row.value, row.unit),
+                }
+            }
+        }
+        Format::Json => {
+            let values: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|row| {
+                    let mut value = json!({
+                        "time": row.time.to_rfc3339(),
+                        value_header: row.value,
+                        "unit": row.unit,
+                    });
+                    if let Some(series) = &row.series {
+                        value["type"] = json!(series);
+                    }
+                    value
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&values)?);
+        }
+        Format::Table => {
+            if with_series {
+                println!("{:<25} {:<5} {:>12} unit", "time", "type", value_header);
+            } else {
+                println!("{:<25} {:>12} unit", "time", value_header);
+            }
+            for row in rows {
+                let time = row.time.format("%Y-%m-%d %H:%M %Z");
+                match &row.series {
+                    Some(series) => println!("{:<25} {:<5} {:>12.2} {}", time, series, row.value, row.unit),
+                    None => println!("{:<25} {:>12.2} {}", time, row.value, row.unit),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Helsinki;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_local_time() {
+        assert_eq!(parse_local_time("2024-01-01", &Helsinki).unwrap(), Utc.ymd(2023, 12, 31).and_hms(22, 0, 0));
+        assert_eq!(parse_local_time("2024-07-01T06:00", &Helsinki).unwrap(), Utc.ymd(2024, 7, 1).and_hms(3, 0, 0));
+        assert!(parse_local_time("01.01.2024", &Helsinki).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use serde_xml_rs::from_str;
+
+use crate::models::*;
+
+/// Any document the ENTSO-E Transparency Platform API can respond with,
+/// keyed by the XML root element.
+#[derive(Debug, PartialEq)]
+pub enum EntsoeDocument {
+    Publication(PublicationMarketDocument),
+    GL(GlMarketDocument),
+    Balancing(BalancingMarketDocument),
+    Unavailability(UnavailabilityMarketDocument),
+    Acknowledgement(AcknowledgementMarketDocument),
+    TransmissionNetwork(TransmissionNetworkMarketDocument),
+}
+
+impl EntsoeDocument {
+    pub fn root_element(&self) -> &'static str {
+        match self {
+            EntsoeDocument::Publication(_) => "Publication_MarketDocument",
+            EntsoeDocument::GL(_) => "GL_MarketDocument",
+            EntsoeDocument::Balancing(_) => "Balancing_MarketDocument",
+            EntsoeDocument::Unavailability(_) => "Unavailability_MarketDocument",
+            EntsoeDocument::Acknowledgement(_) => "Acknowledgement_MarketDocument",
+            EntsoeDocument::TransmissionNetwork(_) => "TransmissionNetwork_MarketDocument",
+        }
+    }
+}
+
+/// Deserializes the document into the type matching its root element.
+pub fn parse_document(document: &str) -> Result<EntsoeDocument, anyhow::Error> {
+    let root = root_element_name(document)
+        .ok_or_else(|| anyhow::anyhow!("No root element found in the document"))?;
+    debug!("Parsing document with root element {}", root);
+
+    let parsed = match root {
+        "Publication_MarketDocument" => EntsoeDocument::Publication(from_str(document)?),
+        "GL_MarketDocument" => EntsoeDocument::GL(from_str(document)?),
+        "Balancing_MarketDocument" => EntsoeDocument::Balancing(from_str(document)?),
+        "Unavailability_MarketDocument" => EntsoeDocument::Unavailability(from_str(document)?),
+        "Acknowledgement_MarketDocument" => EntsoeDocument::Acknowledgement(from_str(document)?),
+        "TransmissionNetwork_MarketDocument" => {
+            EntsoeDocument::TransmissionNetwork(from_str(document)?)
+        }
+        _ => return Err(anyhow::anyhow!("Unsupported document type {}", root)),
+    };
+
+    Ok(parsed)
+}
+
+/// Name of the first element in the document without the namespace prefix,
+/// skipping the XML declaration, comments and processing instructions.
+fn root_element_name(document: &str) -> Option<&str> {
+    let mut rest = document;
+    while let Some(index) = rest.find('<') {
+        rest = &rest[index + 1..];
+        if rest.starts_with('?') || rest.starts_with('!') {
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        let name = name.rsplit(':').next().unwrap_or(name);
+        if name.is_empty() {
+            return None;
+        }
+
+        return Some(name);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_element_name() {
+        assert_eq!(
+            root_element_name(r#"<?xml version="1.0"?><!-- comment --><GL_MarketDocument xmlns="x">"#),
+            Some("GL_MarketDocument")
+        );
+        assert_eq!(
+            root_element_name("<ns2:Acknowledgement_MarketDocument>"),
+            Some("Acknowledgement_MarketDocument")
+        );
+        assert_eq!(root_element_name("not xml"), None);
+    }
+
+    #[test]
+    fn test_parse_gl_document() {
+        let document = r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <GL_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-6:generationloaddocument:3:0">
+            <mRID>changed</mRID>
+            <revisionNumber>1</revisionNumber>
+            <type>A65</type>
+            <process.processType>A16</process.processType>
+            <sender_MarketParticipant.mRID codingScheme="A01">10X1001A1001A450</sender_MarketParticipant.mRID>
+            <sender_MarketParticipant.marketRole.type>A32</sender_MarketParticipant.marketRole.type>
+            <receiver_MarketParticipant.mRID codingScheme="A01">10X1001A1001A450</receiver_MarketParticipant.mRID>
+            <receiver_MarketParticipant.marketRole.type>A33</receiver_MarketParticipant.marketRole.type>
+            <createdDateTime>2024-01-02T10:00:00Z</createdDateTime>
+            <time_Period.timeInterval>
+                <start>2023-12-31T22:00Z</start>
+                <end>2024-01-01T00:00Z</end>
+            </time_Period.timeInterval>
+            <TimeSeries>
+                <mRID>1</mRID>
+                <businessType>A04</businessType>
+                <objectAggregation>A01</objectAggregation>
+                <outBiddingZone_Domain.mRID codingScheme="A01">10YFI-1--------U</outBiddingZone_Domain.mRID>
+                <quantity_Measure_Unit.name>MAW</quantity_Measure_Unit.name>
+                <curveType>A01</curveType>
+                <Period>
+                    <timeInterval>
+                        <start>2023-12-31T22:00Z</start>
+                        <end>2024-01-01T00:00Z</end>
+                    </timeInterval>
+                    <resolution>PT60M</resolution>
+                    <Point>
+                        <position>1</position>
+                        <quantity>10012</quantity>
+                    </Point>
+                    <Point>
+                        <position>2</position>
+                        <quantity>9876</quantity>
+                    </Point>
+                </Period>
+            </TimeSeries>
+        </GL_MarketDocument>"#;
+
+        let parsed = parse_document(document).unwrap();
+        assert_eq!(parsed.root_element(), "GL_MarketDocument");
+        match parsed {
+            EntsoeDocument::GL(document) => {
+                assert_eq!(document.r#type, "A65");
+                let series = &document.time_series[0];
+                assert_eq!(series.out_bidding_zone_domain.as_ref().unwrap().value, "10YFI-1--------U");
+                assert_eq!(series.period[0].point.len(), 2);
+                assert_eq!(series.period[0].point[1].quantity, 9876.0);
+            }
+            other => panic!("Unexpected document {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_acknowledgement_document() {
+        let document = r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <Acknowledgement_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-1:acknowledgementdocument:7:0">
+            <mRID>changed</mRID>
+            <createdDateTime>2024-01-02T10:00:00Z</createdDateTime>
+            <sender_MarketParticipant.mRID codingScheme="A01">10X1001A1001A450</sender_MarketParticipant.mRID>
+            <sender_MarketParticipant.marketRole.type>A32</sender_MarketParticipant.marketRole.type>
+            <receiver_MarketParticipant.mRID codingScheme="A01">10X1001A1001A39I</receiver_MarketParticipant.mRID>
+            <receiver_MarketParticipant.marketRole.type>A39</receiver_MarketParticipant.marketRole.type>
+            <received_MarketDocument.createdDateTime>2024-01-02T10:00:00Z</received_MarketDocument.createdDateTime>
+            <Reason>
+                <code>999</code>
+                <text>No matching data found for Data item Day-ahead Prices [12.1.D]</text>
+            </Reason>
+        </Acknowledgement_MarketDocument>"#;
+
+        match parse_document(document).unwrap() {
+            EntsoeDocument::Acknowledgement(document) => {
+                assert_eq!(document.reason.len(), 1);
+                assert_eq!(document.reason[0].code, "999");
+            }
+            other => panic!("Unexpected document {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_unsupported_document() {
+        let result = parse_document(r#"<?xml version="1.0"?><Criteria_MarketDocument></Criteria_MarketDocument>"#);
+        assert!(result.is_err());
+    }
+}
@@ -1,17 +1,55 @@
 #[macro_use]
 extern crate log;
 
+pub mod area;
+pub mod document;
 pub mod models;
+pub mod period;
+pub mod query;
 
+pub use area::*;
+pub use document::*;
 use http::{StatusCode, header::USER_AGENT};
 pub use models::*;
+pub use period::*;
+pub use query::*;
 use serde_xml_rs::from_str;
 
 const API_URL: &str = r#"https://web-api.tp.entsoe.eu/api"#;
 
 pub async fn day_ahead_prices(security_token: &str, in_domain: &str, out_domain: &str, time_interval: &str) -> Result<PublicationMarketDocument, anyhow::Error> {
+    let (status, data_str) = get(security_token, &[("documentType", "A44"), ("in_Domain", in_domain), ("out_Domain", out_domain), ("TimeInterval", time_interval)]).await?;
+
+    if status != StatusCode::OK {
+        return Err(anyhow::anyhow!(data_str));
+    }
+
+    let data: PublicationMarketDocument = from_str(&data_str)?;
+    debug!("PublicationMarketDocument: {:#?}", data);
+
+    Ok(data)
+}
+
+/// Runs an arbitrary query against the API and returns the response as whichever document type it turned out to be.
+/// Rejected queries are returned as `EntsoeDocument::Acknowledgement` when the API explains the reason.
+pub async fn query(security_token: &str, params: &[(&str, &str)]) -> Result<EntsoeDocument, anyhow::Error> {
+    let (status, data_str) = get(security_token, params).await?;
+
+    match parse_document(&data_str) {
+        Ok(document) => {
+            debug!("{}: {:#?}", document.root_element(), document);
+            Ok(document)
+        }
+        Err(_) if status != StatusCode::OK => Err(anyhow::anyhow!(data_str)),
+        Err(err) => Err(err),
+    }
+}
+
+async fn get(security_token: &str, params: &[(&str, &str)]) -> Result<(StatusCode, String), anyhow::Error> {
     let res = reqwest::Client::new()
-        .get(format!("{}?securityToken={}&documentType={}&in_Domain={}&out_Domain={}&TimeInterval={}", API_URL, security_token, "A44", in_domain, out_domain, time_interval))
+        .get(API_URL)
+        .query(&[("securityToken", security_token)])
+        .query(params)
         .header(USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:101.0) Gecko/20100101 Firefox/101.0")
         .send()
         .await?;
@@ -23,14 +61,7 @@ pub async fn day_ahead_prices(security_token: &str, in_domain: &str, out_domain:
         .await?;
     debug!("{}", data_str);
 
-    if status != StatusCode::OK {
-        return Err(anyhow::anyhow!(data_str));
-    }
-
-    let data: PublicationMarketDocument = from_str(&data_str)?;
-    debug!("PublicationMarketDocument: {:#?}", data);
-
-    Ok(data)
+    Ok((status, data_str))
 }
 
 #[cfg(test)]
@@ -50,7 +81,7 @@ mod tests {
         let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
         let time_interval = "2022-06-30T21:00Z/2022-07-31T21:00Z";
 
-        let response = day_ahead_prices(&security_token, &in_domain, &out_domain, &time_interval).await.unwrap();
+        let response = day_ahead_prices(&security_token, &in_domain, &out_domain, time_interval).await.unwrap();
         info!("Document created at {}", response.created_date_time_as_utc().unwrap());
 
         for time_serie in response.time_series.iter() {
@@ -117,3 +117,298 @@ pub struct Point {
     #[serde(rename = "price.amount")]
     pub price: f32,
 }
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "GL_MarketDocument")]
+pub struct GlMarketDocument {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "revisionNumber")]
+    pub revision_number: String,
+    pub r#type: String,
+    #[serde(rename = "process.processType")]
+    pub process_type: Option<String>,
+    #[serde(rename = "sender_MarketParticipant.mRID")]
+    pub sender_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "sender_MarketParticipant.marketRole.type")]
+    pub sender_market_participant_market_role_type: String,
+    #[serde(rename = "receiver_MarketParticipant.mRID")]
+    pub receiver_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "receiver_MarketParticipant.marketRole.type")]
+    pub receiver_market_participant_market_role_type: String,
+    #[serde(rename = "createdDateTime")]
+    pub created_date_time: String,
+    #[serde(rename = "time_Period.timeInterval")]
+    pub time_interval: TimeInterval,
+    #[serde(rename = "TimeSeries", default)]
+    pub time_series: Vec<GlTimeSeries>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct GlTimeSeries {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "businessType")]
+    pub business_type: String,
+    #[serde(rename = "objectAggregation")]
+    pub object_aggregation: Option<String>,
+    #[serde(rename = "inBiddingZone_Domain.mRID")]
+    pub in_bidding_zone_domain: Option<Domain>,
+    #[serde(rename = "outBiddingZone_Domain.mRID")]
+    pub out_bidding_zone_domain: Option<Domain>,
+    #[serde(rename = "quantity_Measure_Unit.name")]
+    pub quantity_measure_unit: String,
+    #[serde(rename = "curveType")]
+    pub curve_type: String,
+    #[serde(rename = "MktPSRType")]
+    pub mkt_psr_type: Option<MktPsrType>,
+    #[serde(rename = "Period")]
+    pub period: Vec<QuantityPeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MktPsrType {
+    #[serde(rename = "psrType")]
+    pub psr_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct QuantityPeriod {
+    #[serde(rename = "timeInterval")]
+    pub time_interval: TimeInterval,
+    pub resolution: String,
+    #[serde(rename = "Point")]
+    pub point: Vec<QuantityPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct QuantityPoint {
+    pub position: i32,
+    pub quantity: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "Balancing_MarketDocument")]
+pub struct BalancingMarketDocument {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "revisionNumber")]
+    pub revision_number: Option<String>,
+    pub r#type: String,
+    #[serde(rename = "process.processType")]
+    pub process_type: Option<String>,
+    #[serde(rename = "sender_MarketParticipant.mRID")]
+    pub sender_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "sender_MarketParticipant.marketRole.type")]
+    pub sender_market_participant_market_role_type: String,
+    #[serde(rename = "receiver_MarketParticipant.mRID")]
+    pub receiver_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "receiver_MarketParticipant.marketRole.type")]
+    pub receiver_market_participant_market_role_type: String,
+    #[serde(rename = "createdDateTime")]
+    pub created_date_time: String,
+    #[serde(rename = "area_Domain.mRID")]
+    pub area_domain: Option<Domain>,
+    #[serde(rename = "period.timeInterval")]
+    pub time_interval: TimeInterval,
+    #[serde(rename = "TimeSeries", default)]
+    pub time_series: Vec<BalancingTimeSeries>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BalancingTimeSeries {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "businessType")]
+    pub business_type: String,
+    #[serde(rename = "flowDirection.direction")]
+    pub flow_direction: Option<String>,
+    #[serde(rename = "currency_Unit.name")]
+    pub currency_unit: Option<String>,
+    #[serde(rename = "quantity_Measure_Unit.name")]
+    pub quantity_measure_unit: Option<String>,
+    #[serde(rename = "price_Measure_Unit.name")]
+    pub price_measure_unit: Option<String>,
+    #[serde(rename = "curveType")]
+    pub curve_type: String,
+    #[serde(rename = "Period")]
+    pub period: Vec<BalancingPeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BalancingPeriod {
+    #[serde(rename = "timeInterval")]
+    pub time_interval: TimeInterval,
+    pub resolution: String,
+    #[serde(rename = "Point")]
+    pub point: Vec<BalancingPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BalancingPoint {
+    pub position: i32,
+    pub quantity: Option<f64>,
+    #[serde(rename = "procurement_Price.amount")]
+    pub procurement_price: Option<f64>,
+    #[serde(rename = "activation_Price.amount")]
+    pub activation_price: Option<f64>,
+    #[serde(rename = "imbalance_Price.amount")]
+    pub imbalance_price: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "Unavailability_MarketDocument")]
+pub struct UnavailabilityMarketDocument {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "revisionNumber")]
+    pub revision_number: String,
+    pub r#type: String,
+    #[serde(rename = "process.processType")]
+    pub process_type: Option<String>,
+    #[serde(rename = "createdDateTime")]
+    pub created_date_time: String,
+    #[serde(rename = "sender_MarketParticipant.mRID")]
+    pub sender_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "sender_MarketParticipant.marketRole.type")]
+    pub sender_market_participant_market_role_type: String,
+    #[serde(rename = "receiver_MarketParticipant.mRID")]
+    pub receiver_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "receiver_MarketParticipant.marketRole.type")]
+    pub receiver_market_participant_market_role_type: String,
+    #[serde(rename = "unavailability_Time_Period.timeInterval")]
+    pub time_interval: TimeInterval,
+    #[serde(rename = "docStatus")]
+    pub doc_status: Option<DocStatus>,
+    #[serde(rename = "TimeSeries", default)]
+    pub time_series: Vec<UnavailabilityTimeSeries>,
+    #[serde(rename = "Reason", default)]
+    pub reason: Vec<Reason>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DocStatus {
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct UnavailabilityTimeSeries {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "businessType")]
+    pub business_type: String,
+    #[serde(rename = "biddingZone_Domain.mRID")]
+    pub bidding_zone_domain: Option<Domain>,
+    #[serde(rename = "in_Domain.mRID")]
+    pub in_domain: Option<Domain>,
+    #[serde(rename = "out_Domain.mRID")]
+    pub out_domain: Option<Domain>,
+    #[serde(rename = "start_DateAndOrTime.date")]
+    pub start_date: String,
+    #[serde(rename = "start_DateAndOrTime.time")]
+    pub start_time: Option<String>,
+    #[serde(rename = "end_DateAndOrTime.date")]
+    pub end_date: String,
+    #[serde(rename = "end_DateAndOrTime.time")]
+    pub end_time: Option<String>,
+    #[serde(rename = "quantity_Measure_Unit.name")]
+    pub quantity_measure_unit: String,
+    #[serde(rename = "curveType")]
+    pub curve_type: String,
+    #[serde(rename = "production_RegisteredResource.name")]
+    pub production_registered_resource_name: Option<String>,
+    #[serde(rename = "Available_Period", default)]
+    pub available_period: Vec<QuantityPeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "Acknowledgement_MarketDocument")]
+pub struct AcknowledgementMarketDocument {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "createdDateTime")]
+    pub created_date_time: String,
+    #[serde(rename = "sender_MarketParticipant.mRID")]
+    pub sender_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "sender_MarketParticipant.marketRole.type")]
+    pub sender_market_participant_market_role_type: String,
+    #[serde(rename = "receiver_MarketParticipant.mRID")]
+    pub receiver_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "receiver_MarketParticipant.marketRole.type")]
+    pub receiver_market_participant_market_role_type: String,
+    #[serde(rename = "received_MarketDocument.createdDateTime")]
+    pub received_market_document_created_date_time: Option<String>,
+    #[serde(rename = "Reason", default)]
+    pub reason: Vec<Reason>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Reason {
+    pub code: String,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "TransmissionNetwork_MarketDocument")]
+pub struct TransmissionNetworkMarketDocument {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "revisionNumber")]
+    pub revision_number: String,
+    pub r#type: String,
+    #[serde(rename = "process.processType")]
+    pub process_type: Option<String>,
+    #[serde(rename = "sender_MarketParticipant.mRID")]
+    pub sender_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "sender_MarketParticipant.marketRole.type")]
+    pub sender_market_participant_market_role_type: String,
+    #[serde(rename = "receiver_MarketParticipant.mRID")]
+    pub receiver_market_participant_m_rid: MarketParticipantMRid,
+    #[serde(rename = "receiver_MarketParticipant.marketRole.type")]
+    pub receiver_market_participant_market_role_type: String,
+    #[serde(rename = "createdDateTime")]
+    pub created_date_time: String,
+    #[serde(rename = "period.timeInterval")]
+    pub time_interval: TimeInterval,
+    #[serde(rename = "TimeSeries", default)]
+    pub time_series: Vec<TransmissionNetworkTimeSeries>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransmissionNetworkTimeSeries {
+    #[serde(rename = "mRID")]
+    pub m_rid: String,
+    #[serde(rename = "businessType")]
+    pub business_type: String,
+    #[serde(rename = "in_Domain.mRID")]
+    pub in_domain: Option<Domain>,
+    #[serde(rename = "out_Domain.mRID")]
+    pub out_domain: Option<Domain>,
+    #[serde(rename = "currency_Unit.name")]
+    pub currency_unit: Option<String>,
+    #[serde(rename = "quantity_Measure_Unit.name")]
+    pub quantity_measure_unit: Option<String>,
+    #[serde(rename = "price_Measure_Unit.name")]
+    pub price_measure_unit: Option<String>,
+    #[serde(rename = "curveType")]
+    pub curve_type: String,
+    #[serde(rename = "Period")]
+    pub period: Vec<TransmissionNetworkPeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransmissionNetworkPeriod {
+    #[serde(rename = "timeInterval")]
+    pub time_interval: TimeInterval,
+    pub resolution: String,
+    #[serde(rename = "Point")]
+    pub point: Vec<TransmissionNetworkPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TransmissionNetworkPoint {
+    pub position: i32,
+    pub quantity: Option<f64>,
+    #[serde(rename = "price.amount")]
+    pub price: Option<f64>,
+}
@@ -0,0 +1,72 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use iso8601_duration::Duration as IsoDuration;
+
+use crate::TimeInterval;
+
+/// Step of a period, e.g. `PT15M`. Zero and unparseable resolutions are errors.
+pub fn parse_resolution(resolution: &str) -> Result<Duration, anyhow::Error> {
+    IsoDuration::from_str(resolution)
+        .ok()
+        .and_then(|duration| duration.to_std())
+        .map(|duration| Duration::seconds(duration.as_secs() as i64))
+        .filter(|duration| duration.num_seconds() > 0)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse resolution {}", resolution))
+}
+
+/// Expands the points of a period into one value per resolution step, from `(position, value)` pairs.
+/// Positions left out of the document (curve type A03) repeat the previous value.
+pub fn expand_period<T: Copy>(
+    time_interval: &TimeInterval,
+    resolution: &str,
+    points: &[(i32, T)],
+) -> Result<Vec<(DateTime<Utc>, T)>, anyhow::Error> {
+    let (start, end) = match (time_interval.start_as_utc(), time_interval.end_as_utc()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(anyhow::anyhow!("Failed to parse period {:?}", time_interval)),
+    };
+    let resolution = parse_resolution(resolution)?;
+
+    let mut values = Vec::new();
+    let mut last_value = None;
+    let mut current_time = start;
+    while current_time < end {
+        let position = ((current_time - start).num_seconds() / resolution.num_seconds()) as i32 + 1;
+        if let Some((_, value)) = points.iter().find(|(p, _)| *p == position) {
+            last_value = Some(*value);
+        }
+        if let Some(value) = last_value {
+            values.push((current_time, value));
+        }
+        current_time = current_time + resolution;
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time_interval() -> TimeInterval {
+        TimeInterval {
+            start: "2024-01-01T00:00Z".to_string(),
+            end: "2024-01-01T01:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_expand_period_repeats_missing_positions() {
+        let values = expand_period(&time_interval(), "PT15M", &[(1, 10.0), (3, 30.0)]).unwrap();
+
+        let values: Vec<f64> = values.into_iter().map(|(_, value)| value).collect();
+        assert_eq!(values, vec![10.0, 10.0, 30.0, 30.0]);
+    }
+
+    #[test]
+    fn test_expand_period_rejects_invalid_resolutions() {
+        assert!(expand_period(&time_interval(), "PT0M", &[(1, 10.0)]).is_err());
+        assert!(expand_period(&time_interval(), "hourly", &[(1, 10.0)]).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+
+use crate::document::EntsoeDocument;
+
+/// Typed builder for the Transparency Platform API query parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    params: Vec<(&'static str, String)>,
+}
+
+impl Query {
+    pub fn new(document_type: &str) -> Self {
+        Query { params: vec![("documentType", document_type.to_string())] }
+    }
+
+    /// Day-ahead prices [12.1.D], returned as `Publication_MarketDocument`.
+    pub fn day_ahead_prices(in_domain: &str, out_domain: &str) -> Self {
+        Query::new("A44")
+            .param("in_Domain", in_domain)
+            .param("out_Domain", out_domain)
+    }
+
+    /// Actual total load [6.1.A], returned as `GL_MarketDocument`.
+    pub fn actual_total_load(bidding_zone: &str) -> Self {
+        Query::new("A65")
+            .param("processType", "A16")
+            .param("outBiddingZone_Domain", bidding_zone)
+    }
+
+    /// Day-ahead total load forecast [6.1.B], returned as `GL_MarketDocument`.
+    pub fn day_ahead_total_load_forecast(bidding_zone: &str) -> Self {
+        Query::new("A65")
+            .param("processType", "A01")
+            .param("outBiddingZone_Domain", bidding_zone)
+    }
+
+    /// Actual generation per production type [16.1.B&C], returned as `GL_MarketDocument`.
+    pub fn actual_generation_per_type(bidding_zone: &str) -> Self {
+        Query::new("A75")
+            .param("processType", "A16")
+            .param("in_Domain", bidding_zone)
+    }
+
+    pub fn param(mut self, key: &'static str, value: &str) -> Self {
+        match self.params.iter_mut().find(|(existing, _)| *existing == key) {
+            Some(param) => param.1 = value.to_string(),
+            None => self.params.push((key, value.to_string())),
+        }
+        self
+    }
+
+    pub fn time_interval(self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        let time_interval = format!(
+            "{}/{}",
+            start.format("%Y-%m-%dT%H:%MZ"),
+            end.format("%Y-%m-%dT%H:%MZ")
+        );
+        self.param("TimeInterval", &time_interval)
+    }
+
+    pub fn params(&self) -> Vec<(&str, &str)> {
+        self.params
+            .iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .collect()
+    }
+
+    pub async fn send(&self, security_token: &str) -> Result<EntsoeDocument, anyhow::Error> {
+        crate::query(security_token, &self.params()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_query_params() {
+        let query = Query::day_ahead_prices("10YFI-1--------U", "10YFI-1--------U")
+            .time_interval(Utc.ymd(2022, 6, 30).and_hms(21, 0, 0), Utc.ymd(2022, 7, 31).and_hms(21, 0, 0));
+
+        assert_eq!(
+            query.params(),
+            vec![
+                ("documentType", "A44"),
+                ("in_Domain", "10YFI-1--------U"),
+                ("out_Domain", "10YFI-1--------U"),
+                ("TimeInterval", "2022-06-30T21:00Z/2022-07-31T21:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_param_replaces_existing() {
+        let query = Query::actual_total_load("10YFI-1--------U").param("processType", "A01");
+
+        assert_eq!(query, Query::day_ahead_total_load_forecast("10YFI-1--------U"));
+    }
+}
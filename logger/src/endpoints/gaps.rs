@@ -0,0 +1,57 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use crate::{settings::app_config::AppConfig, storage::gaps::scan_gaps, storage::price_store::PriceStores};
+
+#[derive(Deserialize)]
+pub struct GapParams {
+    start: Option<String>,
+    stop: Option<String>,
+    in_domain: Option<String>,
+    out_domain: Option<String>,
+}
+
+/// Missing prices by zone and store `/gaps`, every configured zone unless the domains are given
+#[get("/gaps")]
+pub async fn gaps(
+    params: web::Query<GapParams>,
+    config: web::Data<AppConfig>,
+    stores: web::Data<PriceStores>,
+) -> impl Responder {
+    let zones = match (&params.in_domain, &params.out_domain) {
+        (Some(in_domain), Some(out_domain)) => {
+            if let Err(response) = super::validate_domains(in_domain, out_domain) {
+                return response;
+            }
+            vec![config.zone(in_domain, out_domain)]
+        }
+        (None, None) => config.zones(),
+        _ => return HttpResponse::BadRequest().body("in_domain and out_domain must be given together"),
+    };
+
+    let parse = |value: &Option<String>| -> Result<Option<DateTime<Utc>>, chrono::ParseError> {
+        match value {
+            Some(value) => NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%MZ")
+                .map(|time| Some(DateTime::<Utc>::from_utc(time, Utc))),
+            None => Ok(None),
+        }
+    };
+    let (start, stop) = match (parse(&params.start), parse(&params.stop)) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        _ => return HttpResponse::BadRequest().body("Invalid date format"),
+    };
+
+    let mut reports = Vec::new();
+    for zone in zones.iter() {
+        match scan_gaps(&stores, zone, start, stop).await {
+            Ok(report) => reports.push(report),
+            Err(err) => {
+                error!("Error looking for gaps: {:?}", err);
+                return HttpResponse::InternalServerError().body(err.to_string());
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(reports)
+}
@@ -1,6 +1,19 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::storage::price_store::PriceStores;
 
 #[get("/health")]
 pub async fn health_check() -> impl Responder {
-    return HttpResponse::Ok().json("Healthy")
+    HttpResponse::Ok().json("Healthy")
+}
+
+/// Reachability of the enabled price stores `/health/stores`
+#[get("/health/stores")]
+pub async fn stores_health(stores: web::Data<PriceStores>) -> impl Responder {
+    let health = stores.health().await;
+    if health.iter().all(|store| store.healthy) {
+        HttpResponse::Ok().json(health)
+    } else {
+        HttpResponse::ServiceUnavailable().json(health)
+    }
 }
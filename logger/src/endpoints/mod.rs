@@ -1,2 +1,44 @@
+use actix_web::HttpResponse;
+
 pub mod post;
-pub mod health;
\ No newline at end of file
+pub mod gaps;
+pub mod health;
+pub mod retax;
+pub mod schedule;
+pub mod settings;
+
+/// Rejects domains that aren't EIC codes before they reach the store queries.
+fn validate_domains(in_domain: &str, out_domain: &str) -> Result<(), HttpResponse> {
+    for (name, value) in [("in_domain", in_domain), ("out_domain", out_domain)].iter() {
+        if !api::is_eic_code(value) {
+            return Err(HttpResponse::BadRequest().body(format!("{} {:?} is not an EIC code", name, value)));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_domains() {
+        assert!(validate_domains("10YFI-1--------U", "10YFI-1--------U").is_ok());
+
+        for malicious in [
+            "10YFI-1--------U' OR '1'='1",
+            "x'; DELETE FROM dayAheadPrices WHERE '1'='1",
+            "10YFI-1--------U\\",
+            "\" OR r._field != \"",
+            "",
+        ]
+        .iter()
+        {
+            let response = validate_domains("10YFI-1--------U", malicious).unwrap_err();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", malicious);
+        }
+    }
+}
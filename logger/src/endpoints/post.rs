@@ -5,7 +5,8 @@ use chrono::{DateTime, Duration, NaiveDateTime, ParseError, Utc};
 use serde::Deserialize;
 
 use crate::{
-    entsoapi::fetch::fetch_prices_for_interval, storage::timescaledb::timescale::refresh_views,
+    entsoapi::fetch::fetch_prices_for_interval, settings::app_config::AppConfig,
+    settings::watcher::SettingsStore, storage::price_store::PriceStores,
 };
 
 #[derive(Deserialize)]
@@ -18,17 +19,30 @@ pub struct TimeParams {
 
 /// Update day ahead price data `/dayahead`
 #[post("/dayahead")]
-pub async fn update_dayahead_prices(params: web::Json<TimeParams>) -> impl Responder {
+pub async fn update_dayahead_prices(
+    params: web::Json<TimeParams>,
+    config: web::Data<AppConfig>,
+    stores: web::Data<PriceStores>,
+    settings: web::Data<SettingsStore>,
+) -> impl Responder {
     debug!("update_dayahead_prices requqest inbound");
-    let security_token = dotenv::var("SECURITY_TOKEN").unwrap();
+    let default_zone = config.default_zone();
     let in_domain = params
         .in_domain
         .clone()
-        .unwrap_or(dotenv::var("IN_DOMAIN").unwrap());
+        .or_else(|| default_zone.as_ref().map(|zone| zone.in_domain.clone()));
     let out_domain = params
         .out_domain
         .clone()
-        .unwrap_or(dotenv::var("OUT_DOMAIN").unwrap());
+        .or_else(|| default_zone.as_ref().map(|zone| zone.out_domain.clone()));
+
+    let (in_domain, out_domain) = match (in_domain, out_domain) {
+        (Some(in_domain), Some(out_domain)) => (in_domain, out_domain),
+        _ => return HttpResponse::BadRequest().body("in_domain and out_domain are required"),
+    };
+    if let Err(response) = super::validate_domains(&in_domain, &out_domain) {
+        return response;
+    }
 
     let start: Result<NaiveDateTime, ParseError> =
         NaiveDateTime::parse_from_str(&params.start, "%Y-%m-%dT%H:%MZ");
@@ -42,9 +56,7 @@ pub async fn update_dayahead_prices(params: web::Json<TimeParams>) -> impl Respo
     let start: DateTime<Utc> = DateTime::from_utc(start.unwrap(), Utc);
     let stop: DateTime<Utc> = DateTime::from_utc(stop.unwrap(), Utc);
 
-    // let start = start.unwrap();
-    // let stop = stop.unwrap();
-
+    let settings = settings.current();
     let max_duration = Duration::days(370);
     let mut current_start = start;
 
@@ -52,32 +64,26 @@ pub async fn update_dayahead_prices(params: web::Json<TimeParams>) -> impl Respo
         let current_stop = cmp::min(current_start + max_duration, stop);
 
         if let Err(err) = fetch_prices_for_interval(
-            &security_token,
+            &config,
+            &stores,
+            &stores.names(),
+            &settings,
             &in_domain,
             &out_domain,
             &format!(
                 "{}/{}",
-                current_start.format("%Y-%m-%dT%H:%MZ").to_string(),
-                current_stop.format("%Y-%m-%dT%H:%MZ").to_string()
+                current_start.format("%Y-%m-%dT%H:%MZ"),
+                current_stop.format("%Y-%m-%dT%H:%MZ")
             ),
         )
         .await
         {
-            // Handle the error here
             error!("Error fetching prices: {:?}", err);
-            // Return an appropriate response
             return HttpResponse::InternalServerError().body(err.to_string());
         }
 
         current_start = current_stop;
     }
 
-    if let Err(err) = refresh_views().await {
-        // Handle the error here
-        error!("Error refreshing the prices views: {:?}", err);
-        // Return an appropriate response
-        return HttpResponse::InternalServerError().body(err.to_string());
-    }
-
-    return HttpResponse::Ok().body("ok");
+    HttpResponse::Ok().body("ok")
 }
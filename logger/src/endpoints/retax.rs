@@ -0,0 +1,58 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    settings::app_config::AppConfig, settings::watcher::SettingsStore,
+    storage::price_store::PriceStores, storage::retax::retax_prices,
+};
+
+#[derive(Deserialize)]
+pub struct RetaxParams {
+    start: String,
+    stop: String,
+    in_domain: Option<String>,
+    out_domain: Option<String>,
+}
+
+/// Recalculate the stored taxes with the current settings `/retax`
+#[post("/retax")]
+pub async fn retax(
+    params: web::Json<RetaxParams>,
+    config: web::Data<AppConfig>,
+    stores: web::Data<PriceStores>,
+    settings: web::Data<SettingsStore>,
+) -> impl Responder {
+    let default_zone = config.default_zone();
+    let in_domain = params
+        .in_domain
+        .clone()
+        .or_else(|| default_zone.as_ref().map(|zone| zone.in_domain.clone()));
+    let out_domain = params
+        .out_domain
+        .clone()
+        .or_else(|| default_zone.as_ref().map(|zone| zone.out_domain.clone()));
+
+    let (in_domain, out_domain) = match (in_domain, out_domain) {
+        (Some(in_domain), Some(out_domain)) => (in_domain, out_domain),
+        _ => return HttpResponse::BadRequest().body("in_domain and out_domain are required"),
+    };
+    if let Err(response) = super::validate_domains(&in_domain, &out_domain) {
+        return response;
+    }
+
+    let start = NaiveDateTime::parse_from_str(&params.start, "%Y-%m-%dT%H:%MZ");
+    let stop = NaiveDateTime::parse_from_str(&params.stop, "%Y-%m-%dT%H:%MZ");
+    let (start, stop) = match (start, stop) {
+        (Ok(start), Ok(stop)) => (DateTime::<Utc>::from_utc(start, Utc), DateTime::<Utc>::from_utc(stop, Utc)),
+        _ => return HttpResponse::BadRequest().body("Invalid date format"),
+    };
+
+    match retax_prices(&config, &stores, &settings.current(), &in_domain, &out_domain, start, stop).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => {
+            error!("Error recalculating the taxes: {:?}", err);
+            HttpResponse::InternalServerError().body(err.to_string())
+        }
+    }
+}
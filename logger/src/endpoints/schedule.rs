@@ -0,0 +1,9 @@
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::entsoapi::scheduler::Schedules;
+
+/// Last and next fetch of each zone `/schedule`
+#[get("/schedule")]
+pub async fn schedule(schedules: web::Data<Schedules>) -> impl Responder {
+    HttpResponse::Ok().json(schedules.all())
+}
@@ -0,0 +1,9 @@
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::settings::watcher::SettingsStore;
+
+/// Version of the active tax settings `/settings/version`
+#[get("/settings/version")]
+pub async fn settings_version(settings: web::Data<SettingsStore>) -> impl Responder {
+    HttpResponse::Ok().json(settings.version())
+}
@@ -1,87 +1,165 @@
-use std::cmp;
-
-use crate::settings;
-use crate::storage::influxdb::influx::{self, upsert_document_into_influxdb};
-use crate::storage::timescaledb::timescale::{self, upsert_document_into_timescaledb, refresh_views};
-use api::day_ahead_prices;
+use crate::settings::app_config::AppConfig;
+use crate::settings::config_model::{SettingsConfig, ZoneConfig};
+use crate::storage::gaps::{scan_gaps, GapReport};
+use crate::storage::price_store::{document_points, PriceStores};
+use api::{query, EntsoeDocument};
 use chrono::Duration as ChronoDuration;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, DurationRound, NaiveDateTime, TimeZone, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// ENTSO-E returns at most a year of prices per request.
+const MAX_FETCH_DAYS: i64 = 365;
+
+/// Longest wait before a backfill range without prices is fetched again.
+const MAX_EMPTY_BACKOFF_DAYS: i64 = 7;
+
+/// Interval to fetch and the stores to write it to.
+#[derive(Debug, PartialEq)]
+pub struct FetchInterval {
+    pub interval: String,
+    pub stores: Vec<&'static str>,
+}
 
+/// Fetches the prices and writes them to the `targets` stores. Returns the number of prices, none when
+/// ENTSO-E acknowledges the query without a document, e.g. when there's no matching data.
 pub async fn fetch_prices_for_interval(
-    security_token: &str,
+    config: &AppConfig,
+    stores: &PriceStores,
+    targets: &[&'static str],
+    settings: &SettingsConfig,
     in_domain: &str,
     out_domain: &str,
     time_interval: &str,
-) -> Result<(), anyhow::Error> {
+) -> Result<usize, anyhow::Error> {
     info!(
         "Fetching prices for interval {} in domain {}",
         &time_interval, &out_domain
     );
 
-    let config = settings::config::load_settings(format!("configs/{}.yaml", "production"))
-        .expect("Failed to load settings file.");
+    let settings = settings
+        .for_zone(in_domain, out_domain)
+        .with_default_timezone(config.timezone);
 
-    match day_ahead_prices(&security_token, &in_domain, &out_domain, &time_interval).await {
-        Ok(data) => {
-            info!(
-                "Fetched document created at {}",
-                data.created_date_time_as_utc().unwrap()
-            );
-            
-            let timescale_future = upsert_document_into_timescaledb(&data, &in_domain, &out_domain, &config);
-            let influx_future = upsert_document_into_influxdb(&data, &in_domain, &out_domain);
-        
-            let (timescale_result, influx_result) = tokio::join!(timescale_future, influx_future);
-
-            if timescale_result.is_err() {
-                error!("Error inserting into TimescaleDB: {:?}", timescale_result);
-            }
+    let params = [
+        ("documentType", "A44"),
+        ("in_Domain", in_domain),
+        ("out_Domain", out_domain),
+        ("TimeInterval", time_interval),
+    ];
+    let data = match query(&config.security_token, &params).await? {
+        EntsoeDocument::Publication(data) => data,
+        EntsoeDocument::Acknowledgement(acknowledgement) => {
+            let reasons: Vec<&str> = acknowledgement.reason.iter().filter_map(|reason| reason.text.as_deref()).collect();
+            info!("No prices for {}/{} in {}: {}", in_domain, out_domain, time_interval, reasons.join(", "));
+            return Ok(0);
+        }
+        document => return Err(anyhow::anyhow!("Expected prices, got {}", document.root_element())),
+    };
+    info!(
+        "Fetched document created at {}",
+        data.created_date_time_as_utc().unwrap()
+    );
 
-            if influx_result.is_err() {
-                error!("Error inserting into InfluxDB: {:?}", influx_result);
-            }
+    let points = document_points(&data)?;
+    let result = stores.upsert_points(targets, in_domain, out_domain, &points, &settings).await;
 
-            if timescale::is_enabled() {
-                if let Err(err) = refresh_views().await {
-                    // Handle the error here
-                    error!("Error refreshing the prices views: {:?}", err);
-                }
-            }
+    // The stores that were written to are refreshed even if another one failed
+    if let Err(err) = stores.after_write().await {
+        error!("Error refreshing the prices views: {:?}", err);
+    }
+
+    result.map(|_| points.len())
+}
 
-            Ok(())
+/// When to fetch an empty range again and the wait before that, by zone and the range's start.
+type EmptyRanges = HashMap<(String, String, DateTime<Utc>), (DateTime<Utc>, ChronoDuration)>;
+
+/// Backfill ranges that ENTSO-E had no prices for, e.g. a missing publication or a start time before
+/// the platform's data. Each is fetched again after a wait that doubles every time it comes back empty.
+pub struct EmptyFetches {
+    backoff: ChronoDuration,
+    ranges: Mutex<EmptyRanges>,
+}
+
+impl EmptyFetches {
+    /// `backoff` is the first wait, normally the gap scan interval.
+    pub fn new(backoff: ChronoDuration) -> EmptyFetches {
+        EmptyFetches {
+            backoff,
+            ranges: Mutex::new(HashMap::new()),
         }
-        Err(err) => Err(anyhow::anyhow!(err)),
     }
-}
 
-// Example of the format: 2022-06-30T21:00Z/2022-07-31T21:00Z
-pub async fn get_fetch_time_interval(in_domain: &str, out_domain: &str) -> String {
-    let mut start_time = chrono::offset::Utc::now();
-    let naive_time = NaiveDateTime::parse_from_str(
-        &dotenv::var("START_TIME").unwrap_or("".to_string()),
-        "%Y-%m-%dT%H:%MZ",
-    );
-    if naive_time.is_ok() {
-        start_time = Utc.from_utc_datetime(&naive_time.unwrap());
+    /// Whether the range starting at `start` is fetched, i.e. it hasn't come back empty or its wait is over.
+    pub fn is_due(&self, in_domain: &str, out_domain: &str, start: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let ranges = self.ranges.lock().unwrap();
+        ranges
+            .get(&(in_domain.to_string(), out_domain.to_string(), start))
+            .is_none_or(|(retry_at, _)| now >= *retry_at)
     }
 
-    let latest_timescale = timescale::get_latest_time(&in_domain, &out_domain)
-        .await
-        .unwrap_or(start_time);
-    let latest_influx = influx::get_latest_time(&in_domain, &out_domain)
-        .await
-        .unwrap_or(start_time);
+    /// Records the result of fetching the range, `fetched` is the number of prices.
+    pub fn record(&self, in_domain: &str, out_domain: &str, start: DateTime<Utc>, fetched: usize, now: DateTime<Utc>) {
+        let key = (in_domain.to_string(), out_domain.to_string(), start);
+        let mut ranges = self.ranges.lock().unwrap();
+        if fetched > 0 {
+            ranges.remove(&key);
+            return;
+        }
+
+        let wait = match ranges.get(&key) {
+            Some((_, wait)) => (*wait * 2).min(ChronoDuration::days(MAX_EMPTY_BACKOFF_DAYS)),
+            None => self.backoff,
+        };
+        ranges.insert(key, (now + wait, wait));
+    }
+}
 
+/// Each store continues from its own latest price, so a store that's behind doesn't hold back the others.
+/// Stores at the same position share a fetch. A store that can't be reached is an error and isn't fetched for.
+pub async fn get_fetch_time_intervals(
+    config: &AppConfig,
+    stores: &PriceStores,
+    zone: &ZoneConfig,
+) -> Vec<Result<FetchInterval, anyhow::Error>> {
+    let start_time = zone_start_time(zone);
     debug!("Start time: {}", start_time);
-    debug!("Latest TimescaleDB time: {}", latest_timescale);
-    debug!("Latest InfluxDB time: {}", latest_influx);
+    let mut intervals = Vec::new();
+    let mut by_cursor: BTreeMap<DateTime<Utc>, Vec<&'static str>> = BTreeMap::new();
+    for (store, cursor) in stores.cursors(&zone.in_domain, &zone.out_domain, start_time).await {
+        match cursor {
+            Ok(cursor) => by_cursor.entry(cursor).or_default().push(store),
+            Err(err) => intervals.push(Err(anyhow::anyhow!(
+                "{} can't be reached, not fetching {}/{} for it: {}",
+                store,
+                zone.in_domain,
+                zone.out_domain,
+                err
+            ))),
+        }
+    }
+
+    let days: i64 = zone.interval_days.unwrap_or(config.interval_days);
+    for (start_time, stores) in by_cursor {
+        intervals.push(Ok(FetchInterval {
+            interval: fetch_interval(start_time, days),
+            stores,
+        }));
+    }
+
+    intervals
+}
 
-    let start_time = cmp::max(start_time, cmp::min(latest_timescale, latest_influx));
+/// The zone's `start_time`, or now without one.
+fn zone_start_time(zone: &ZoneConfig) -> DateTime<Utc> {
+    NaiveDateTime::parse_from_str(zone.start_time.as_deref().unwrap_or(""), "%Y-%m-%dT%H:%MZ")
+        .map(|start_time| Utc.from_utc_datetime(&start_time))
+        .unwrap_or_else(|_| Utc::now())
+}
 
-    let days: i64 = dotenv::var("INTERVAL_DAYS")
-        .map(|var| var.parse::<i64>())
-        .unwrap_or(Ok(1))
-        .unwrap();
+// Example of the format: 2022-06-30T21:00Z/2022-07-31T21:00Z
+fn fetch_interval(start_time: DateTime<Utc>, days: i64) -> String {
     let end_time = start_time + ChronoDuration::days(days);
     format!(
         "{}/{}",
@@ -90,20 +168,149 @@ pub async fn get_fetch_time_interval(in_domain: &str, out_domain: &str) -> Strin
     )
 }
 
+/// Fetches the prices missing from the stores, in whole hours, and writes them only to the stores missing them.
+/// Ranges that came back empty before are skipped until `empty` has them due again.
+/// Returns the number of fetched intervals.
+pub async fn backfill_gaps(
+    config: &AppConfig,
+    stores: &PriceStores,
+    settings: &SettingsConfig,
+    report: &GapReport,
+    empty: &EmptyFetches,
+) -> Result<usize, anyhow::Error> {
+    let mut fetched = 0;
+
+    for (gap, targets) in report.missing().iter() {
+        let hour = ChronoDuration::hours(1);
+        let mut start = gap.start.duration_trunc(hour)?;
+        let end = gap.end.duration_trunc(hour)?;
+        let end = if end < gap.end { end + hour } else { end };
+
+        while start < end {
+            let chunk_end = end.min(start + ChronoDuration::days(MAX_FETCH_DAYS));
+            if !empty.is_due(&report.in_domain, &report.out_domain, start, Utc::now()) {
+                debug!(
+                    "Not backfilling {}/{} from {} to {} yet, there were no prices last time",
+                    report.in_domain, report.out_domain, start, chunk_end
+                );
+                start = chunk_end;
+                continue;
+            }
+
+            info!(
+                "Backfilling {}/{} from {} to {} in {}",
+                report.in_domain,
+                report.out_domain,
+                start,
+                chunk_end,
+                targets.join(", ")
+            );
+            let prices = fetch_prices_for_interval(
+                config,
+                stores,
+                targets,
+                settings,
+                &report.in_domain,
+                &report.out_domain,
+                &format_interval(start, chunk_end),
+            )
+            .await?;
+            empty.record(&report.in_domain, &report.out_domain, start, prices, Utc::now());
+
+            fetched += 1;
+            start = chunk_end;
+        }
+    }
+
+    Ok(fetched)
+}
+
+/// Scans every zone for gaps and fetches them. Prices after a store's cursor are left to the scheduled fetch.
+/// Errors are logged, the other zones are still backfilled.
+pub async fn backfill_zones(
+    config: &AppConfig,
+    stores: &PriceStores,
+    settings: &SettingsConfig,
+    zones: &[ZoneConfig],
+    empty: &EmptyFetches,
+) {
+    for zone in zones.iter() {
+        let mut report = match scan_gaps(stores, zone, None, None).await {
+            Ok(report) => report,
+            Err(err) => {
+                error!("Error looking for gaps in {}/{}: {:?}", zone.in_domain, zone.out_domain, err);
+                continue;
+            }
+        };
+        let cursors = stores.cursors(&zone.in_domain, &zone.out_domain, zone_start_time(zone)).await;
+        for (store, cursor) in cursors {
+            if let Ok(cursor) = cursor {
+                report.truncate(store, cursor);
+            }
+        }
+
+        let missing = report.missing();
+        if missing.is_empty() {
+            debug!("No gaps in {}/{}", zone.in_domain, zone.out_domain);
+            continue;
+        }
+
+        info!("Found {} gaps in {}/{}", missing.len(), zone.in_domain, zone.out_domain);
+        if let Err(err) = backfill_gaps(config, stores, settings, &report, empty).await {
+            error!("Error backfilling {}/{}: {:?}", zone.in_domain, zone.out_domain, err);
+        }
+    }
+}
+
+fn format_interval(start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    format!("{}/{}", start.format("%Y-%m-%dT%H:%MZ"), end.format("%Y-%m-%dT%H:%MZ"))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::dotenv;
+    use crate::settings::config::load_app_config;
 
     use super::*;
 
+    #[test]
+    fn test_empty_fetches_back_off() {
+        let empty = EmptyFetches::new(ChronoDuration::hours(1));
+        let start = Utc.ymd(2015, 1, 1).and_hms(0, 0, 0);
+        let now = Utc.ymd(2024, 6, 10).and_hms(12, 0, 0);
+        let after = |hours: i64| now + ChronoDuration::hours(hours);
+        let zone = "10YFI-1--------U";
+
+        assert!(empty.is_due(zone, zone, start, now));
+        empty.record(zone, zone, start, 0, now);
+        assert!(!empty.is_due(zone, zone, start, now));
+        assert!(empty.is_due(zone, zone, start, after(1)));
+        assert!(empty.is_due(zone, zone, start + ChronoDuration::hours(1), now));
+
+        // Each empty result doubles the wait, up to a week
+        empty.record(zone, zone, start, 0, after(1));
+        assert!(!empty.is_due(zone, zone, start, after(2)));
+        assert!(empty.is_due(zone, zone, start, after(3)));
+        for _ in 0..10 {
+            empty.record(zone, zone, start, 0, now);
+        }
+        assert!(!empty.is_due(zone, zone, start, now + ChronoDuration::days(7) - ChronoDuration::hours(1)));
+        assert!(empty.is_due(zone, zone, start, now + ChronoDuration::days(7)));
+
+        // Prices reset it
+        empty.record(zone, zone, start, 24, now);
+        assert!(empty.is_due(zone, zone, start, now));
+    }
+
     #[tokio::test]
     async fn test_get_fetch_time_interval() {
         dotenv().ok();
 
-        let in_domain = dotenv::var("IN_DOMAIN").unwrap();
-        let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
+        let config = load_app_config(format!("configs/{}.yaml", "production")).unwrap();
+        let zone = config.default_zone().unwrap();
 
-        let response = get_fetch_time_interval(&in_domain, &out_domain).await;
+        let stores = PriceStores::from_config(&config).unwrap();
+        let response = get_fetch_time_intervals(&config, &stores, &zone).await;
         info!("Fetch interval {:?}", response);
     }
 }
@@ -1 +1,2 @@
-pub mod fetch;
\ No newline at end of file
+pub mod fetch;
+pub mod scheduler;
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::settings::config_model::{ScheduleConfig, ZoneConfig};
+use crate::storage::price_store::PriceStores;
+use crate::storage::timescaledb::aggregates::local_midnight;
+
+/// How far the stored prices of a zone reach.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coverage {
+    /// Published prices are missing, e.g. while catching up from the start time
+    Behind,
+    /// Today's prices are stored, the next day's aren't yet
+    Today,
+    /// The next day's prices are stored too
+    Tomorrow,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneSchedule {
+    pub in_domain: String,
+    pub out_domain: String,
+    pub last_run: Option<DateTime<Utc>>,
+    pub next_run: DateTime<Utc>,
+    /// `None` before the first run or when no store can be reached
+    pub coverage: Option<Coverage>,
+}
+
+/// Next run of each zone, shared with the REST API.
+#[derive(Default)]
+pub struct Schedules {
+    zones: Mutex<BTreeMap<(String, String), ZoneSchedule>>,
+}
+
+impl Schedules {
+    pub fn update(&self, schedule: ZoneSchedule) {
+        self.zones
+            .lock()
+            .unwrap()
+            .insert((schedule.in_domain.clone(), schedule.out_domain.clone()), schedule);
+    }
+
+    pub fn all(&self) -> Vec<ZoneSchedule> {
+        self.zones.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Coverage of the zone's prices by the reachable store furthest behind, in the delivery days of the
+/// schedule's auction. `None` if none of the stores can be reached.
+pub async fn zone_coverage(stores: &PriceStores, zone: &ZoneConfig, schedule: &ScheduleConfig, now: DateTime<Utc>) -> Option<Coverage> {
+    let latest_times = join_all(stores.iter().map(|store| store.latest_time(&zone.in_domain, &zone.out_domain))).await;
+
+    // A store without prices is behind everything, so `None` sorts first
+    let furthest_behind = latest_times.into_iter().filter_map(Result::ok).min()?;
+    let resolution = Duration::minutes(zone.resolution_minutes.unwrap_or(60));
+
+    Some(coverage(furthest_behind, resolution, schedule.publication_timezone, now))
+}
+
+/// The SDAC delivery day runs from midnight to midnight CET for every zone, so a zone west of it
+/// gets the next day's prices up to its own 23:00.
+pub fn coverage(latest_time: Option<DateTime<Utc>>, resolution: Duration, timezone: Tz, now: DateTime<Utc>) -> Coverage {
+    let latest_time = match latest_time {
+        Some(latest_time) => latest_time,
+        None => return Coverage::Behind,
+    };
+
+    let today = now.with_timezone(&timezone).date().naive_local();
+    let stored_until = latest_time + resolution;
+    if stored_until >= local_midnight(timezone, today + Duration::days(2)) {
+        Coverage::Tomorrow
+    } else if stored_until >= local_midnight(timezone, today + Duration::days(1)) {
+        Coverage::Today
+    } else {
+        Coverage::Behind
+    }
+}
+
+/// When to fetch the zone next. While behind, or without a schedule, the zone is fetched every `interval`
+/// milliseconds. Otherwise it's polled in the publication window until the next day's prices are stored,
+/// and then rechecked until the next auction.
+pub fn next_run(schedule: &ScheduleConfig, interval: u64, coverage: Option<Coverage>, now: DateTime<Utc>) -> DateTime<Utc> {
+    let after = |milliseconds: u64| now + Duration::milliseconds(milliseconds as i64);
+    let publication_time = match schedule.publication_time() {
+        Some(publication_time) if schedule.enabled => publication_time,
+        _ => return after(interval),
+    };
+
+    let timezone = schedule.publication_timezone;
+    let today = now.with_timezone(&timezone).date().naive_local();
+    let publication = |date: NaiveDate| local_time(timezone, date.and_time(publication_time));
+    let window_start = publication(today);
+    let window_end = window_start + Duration::minutes(schedule.window_minutes);
+    let next_auction = if now < window_start {
+        window_start
+    } else {
+        publication(today + Duration::days(1))
+    };
+
+    match coverage {
+        None | Some(Coverage::Behind) => after(interval),
+        Some(Coverage::Today) if now >= window_start && now < window_end => after(schedule.poll_interval),
+        Some(_) => next_auction.min(after(schedule.recheck_interval)),
+    }
+}
+
+fn local_time(timezone: Tz, time: NaiveDateTime) -> DateTime<Utc> {
+    timezone
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2024, 6, day).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn test_coverage_in_delivery_days() {
+        let brussels = chrono_tz::Europe::Brussels;
+        let hour = Duration::hours(1);
+        // 2024-06-10 14:00 in Brussels, the delivery day ends at 22:00 UTC
+        let now = utc(10, 12, 0);
+
+        assert_eq!(coverage(None, hour, brussels, now), Coverage::Behind);
+        assert_eq!(coverage(Some(utc(10, 20, 0)), hour, brussels, now), Coverage::Behind);
+        assert_eq!(coverage(Some(utc(10, 21, 0)), hour, brussels, now), Coverage::Today);
+        assert_eq!(coverage(Some(utc(11, 21, 45)), Duration::minutes(15), brussels, now), Coverage::Tomorrow);
+    }
+
+    #[test]
+    fn test_coverage_west_of_the_delivery_day() {
+        // Lisbon's prices end at its 23:00, the delivery day's midnight
+        let schedule = ScheduleConfig::default();
+        let lisbon_morning = utc(10, 6, 0);
+
+        let coverage = |latest_time| coverage(Some(latest_time), Duration::hours(1), schedule.publication_timezone, lisbon_morning);
+        assert_eq!(coverage(utc(10, 21, 0)), Coverage::Today);
+        assert_eq!(coverage(utc(11, 21, 0)), Coverage::Tomorrow);
+    }
+
+    #[test]
+    fn test_next_run_follows_the_publication() {
+        let schedule = ScheduleConfig::default();
+        let interval = 10_000;
+        // 12:45 CEST is 10:45 UTC
+        let publication = utc(10, 10, 45);
+
+        // Catching up isn't held back by the schedule
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Behind), utc(10, 8, 0)), utc(10, 8, 0) + Duration::seconds(10));
+        assert_eq!(next_run(&schedule, interval, None, utc(10, 8, 0)), utc(10, 8, 0) + Duration::seconds(10));
+
+        // Waits for the publication, rechecking in between
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Today), utc(10, 8, 0)), publication);
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Today), utc(10, 2, 0)), utc(10, 8, 0));
+
+        // Polls in the window until the next day's prices are there
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Today), utc(10, 11, 0)), utc(10, 11, 1));
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Tomorrow), utc(10, 11, 0)), utc(10, 17, 0));
+
+        // After the window, rechecks until the next auction
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Today), utc(10, 14, 0)), utc(10, 20, 0));
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Tomorrow), utc(10, 20, 0)), utc(11, 2, 0));
+        assert_eq!(next_run(&schedule, interval, Some(Coverage::Tomorrow), utc(11, 7, 0)), utc(11, 10, 45));
+
+        let disabled = ScheduleConfig {
+            enabled: false,
+            ..ScheduleConfig::default()
+        };
+        assert_eq!(next_run(&disabled, interval, Some(Coverage::Today), utc(10, 11, 0)), utc(10, 11, 0) + Duration::seconds(10));
+    }
+}
@@ -1,15 +1,28 @@
 #[macro_use]
 extern crate log;
 
-use crate::endpoints::{health, post};
+use crate::endpoints::{gaps, health, post, retax, schedule, settings as settings_endpoints};
 use crate::entsoapi::fetch::fetch_prices_for_interval;
-use crate::entsoapi::fetch::get_fetch_time_interval;
-use actix_web::{middleware, App, HttpServer};
-use chrono::{DateTime, Utc};
+use crate::entsoapi::fetch::get_fetch_time_intervals;
+use crate::entsoapi::fetch::{backfill_gaps, backfill_zones, EmptyFetches};
+use crate::entsoapi::scheduler::{next_run, zone_coverage, Schedules, ZoneSchedule};
+use crate::settings::app_config::AppConfig;
+use crate::settings::config_model::ZoneConfig;
+use crate::settings::watcher::SettingsStore;
+use crate::storage::gaps::scan_gaps;
+use crate::storage::price_store::PriceStores;
+use crate::storage::retax::retax_prices;
+use actix_web::{middleware, web, App, HttpServer};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use chrono_tz::Tz;
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
+use futures::future::join_all;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::join;
+use tokio::sync::Semaphore;
 use tokio::time::sleep;
 
 mod endpoints;
@@ -18,66 +31,320 @@ mod logging;
 mod settings;
 mod storage;
 
-fn get_time_after_duration(duration: u64) -> String {
-    let tz_now: DateTime<Tz> = Utc::now().with_timezone(&get_timezone());
-    let time = tz_now + chrono::Duration::milliseconds(duration as i64);
+#[derive(Parser)]
+#[command(about, version)]
+struct Cli {
+    /// Path to the configuration file, overrides the profile
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Configuration profile, loaded from configs/<profile>.yaml
+    #[arg(long, env = "CONFIG_PROFILE", default_value = "production")]
+    profile: String,
 
-    time.format("%Y-%m-%dT%H:%M:%S %Z").to_string()
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn get_timezone() -> Tz {
-    let timezone = dotenv::var("CHRONO_TIMEZONE").unwrap_or("Europe/Helsinki".to_string());
-    timezone.parse().unwrap()
+#[derive(Subcommand)]
+enum Command {
+    /// Recalculate the stored taxes and price components with the current settings, without fetching
+    Retax {
+        /// Start time (inclusive), e.g. 2022-11-30T22:00Z
+        #[arg(long, value_parser = parse_utc)]
+        start: DateTime<Utc>,
+        /// Stop time (exclusive), e.g. 2023-04-30T21:00Z
+        #[arg(long, value_parser = parse_utc)]
+        stop: DateTime<Utc>,
+        /// Defaults to the first configured zone
+        #[arg(long)]
+        in_domain: Option<String>,
+        #[arg(long)]
+        out_domain: Option<String>,
+    },
+    /// Apply the database schema migrations and exit
+    Migrate,
+    /// List the missing prices in each store, every configured zone unless the domains are given
+    Gaps {
+        /// Start time (inclusive), defaults to the zone's start time
+        #[arg(long, value_parser = parse_utc)]
+        start: Option<DateTime<Utc>>,
+        /// Stop time (exclusive), defaults to the latest stored price
+        #[arg(long, value_parser = parse_utc)]
+        stop: Option<DateTime<Utc>>,
+        #[arg(long, requires = "out_domain")]
+        in_domain: Option<String>,
+        #[arg(long, requires = "in_domain")]
+        out_domain: Option<String>,
+        /// Fetch the missing prices
+        #[arg(long)]
+        backfill: bool,
+    },
+}
+
+fn parse_utc(value: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%MZ")
+        .map(|time| DateTime::from_utc(time, Utc))
+        .map_err(|_| "expected the format YYYY-MM-DDTHH:MMZ".to_string())
+}
+
+impl Cli {
+    fn config_path(&self) -> PathBuf {
+        self.config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("configs/{}.yaml", self.profile)))
+    }
+}
+
+fn format_local_time(timezone: &Tz, time: DateTime<Utc>) -> String {
+    time.with_timezone(timezone).format("%Y-%m-%dT%H:%M:%S %Z").to_string()
+}
+
+async fn update_zone(config: &AppConfig, stores: &PriceStores, settings: &SettingsStore, zone: &ZoneConfig) {
+    for fetch in get_fetch_time_intervals(config, stores, zone).await {
+        let fetch = match fetch {
+            Ok(fetch) => fetch,
+            Err(err) => {
+                error!("{:?}", err);
+                continue;
+            }
+        };
+
+        if let Err(err) = fetch_prices_for_interval(
+            config,
+            stores,
+            &fetch.stores,
+            &settings.current(),
+            &zone.in_domain,
+            &zone.out_domain,
+            &fetch.interval,
+        )
+        .await
+        {
+            error!(
+                "Error fetching prices for {}/{} to {}: {:?}",
+                zone.in_domain,
+                zone.out_domain,
+                fetch.stores.join(", "),
+                err
+            );
+        }
+    }
+}
+
+/// Fetches the zone whenever its schedule says so. `fetches` limits the zones fetched at the same time.
+async fn schedule_zone(
+    config: &AppConfig,
+    stores: &PriceStores,
+    settings: &SettingsStore,
+    schedules: &Schedules,
+    fetches: &Semaphore,
+    zone: &ZoneConfig,
+) {
+    let schedule = config.zone_schedule(zone);
+
+    loop {
+        {
+            let _permit = fetches.acquire().await;
+            update_zone(config, stores, settings, zone).await;
+        }
+
+        let now = Utc::now();
+        let coverage = zone_coverage(stores, zone, &schedule, now).await;
+        let next = next_run(&schedule, config.interval, coverage, now);
+        schedules.update(ZoneSchedule {
+            in_domain: zone.in_domain.clone(),
+            out_domain: zone.out_domain.clone(),
+            last_run: Some(now),
+            next_run: next,
+            coverage,
+        });
+
+        info!(
+            "Logging {}/{} done, waiting for the next fetch at {} ...",
+            zone.in_domain,
+            zone.out_domain,
+            format_local_time(&config.timezone, next)
+        );
+        sleep((next - Utc::now()).to_std().unwrap_or_default()).await;
+    }
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
+    let cli = Cli::parse();
+
     logging::init_logging();
 
     info!("ENTSO-E Logger starting");
-    info!("Using time zone: {}", get_timezone().name());
 
-    let config = settings::config::load_settings(format!("configs/{}.yaml", "production"))
-        .expect("Failed to load settings file.");
+    let config_path = cli.config_path();
+    let config = match settings::config::load_app_config(&config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    info!("Using time zone: {}", config.timezone.name());
 
-    if let Err(err) = config.validate() {
-        panic!("Validation error: {}", err);
+    let stores = match PriceStores::from_config(&config) {
+        Ok(stores) => Arc::new(stores),
+        Err(err) => {
+            error!("Failed to set up the price stores: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+    if stores.is_empty() {
+        warn!("No price stores enabled, the fetched prices are not saved anywhere.");
     }
 
-    let interval: u64 = dotenv::var("INTERVAL")
-        .map(|var| var.parse::<u64>())
-        .unwrap_or(Ok(10_000))
-        .unwrap();
+    if let Some(Command::Migrate) = cli.command {
+        match stores.migrate().await {
+            Ok(applied) if applied.is_empty() => println!("The schema is up to date"),
+            Ok(applied) => {
+                for migration in applied.iter() {
+                    println!("Applied {}", migration);
+                }
+            }
+            Err(err) => {
+                error!("Error applying the migrations: {:?}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let security_token = dotenv::var("SECURITY_TOKEN").unwrap();
-    let in_domain = dotenv::var("IN_DOMAIN").unwrap();
-    let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
+    // A database that's down at startup is migrated on the next start, the fetches log their own errors
+    if config.timescaledb.migrate {
+        if let Err(err) = stores.migrate().await {
+            error!("Error applying the migrations: {:?}", err);
+        }
+    }
+
+    if let Some(Command::Retax { start, stop, in_domain, out_domain }) = cli.command {
+        let default_zone = config.default_zone();
+        let in_domain = in_domain.or_else(|| default_zone.as_ref().map(|zone| zone.in_domain.clone()));
+        let out_domain = out_domain.or_else(|| default_zone.as_ref().map(|zone| zone.out_domain.clone()));
+        let (in_domain, out_domain) = match (in_domain, out_domain) {
+            (Some(in_domain), Some(out_domain)) => (in_domain, out_domain),
+            _ => {
+                error!("--in-domain and --out-domain are required when no zones are configured");
+                std::process::exit(1);
+            }
+        };
+
+        match retax_prices(&config, &stores, &config.settings, &in_domain, &out_domain, start, stop).await {
+            Ok(report) => {
+                for (store, changed) in report.changed.iter() {
+                    println!("{}/{}: {} prices changed in {}", report.in_domain, report.out_domain, changed, store);
+                }
+            }
+            Err(err) => {
+                error!("Error recalculating the taxes: {:?}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let run_server: bool = dotenv::var("ENABLE_REST_API")
-        .unwrap_or_else(|_| String::from("false"))
-        .parse()
-        .unwrap_or(true);
+    if let Some(Command::Gaps { start, stop, in_domain, out_domain, backfill }) = cli.command {
+        let zones = match (in_domain, out_domain) {
+            (Some(in_domain), Some(out_domain)) => vec![config.zone(&in_domain, &out_domain)],
+            _ => config.zones(),
+        };
 
-    let run_update: bool = dotenv::var("ENABLE_AUTO_UPDATE")
-        .unwrap_or_else(|_| String::from("false"))
-        .parse()
-        .unwrap_or(true);
+        let mut failed = false;
+        for zone in zones.iter() {
+            let report = match scan_gaps(&stores, zone, start, stop).await {
+                Ok(report) => report,
+                Err(err) => {
+                    error!("{:?}", err);
+                    failed = true;
+                    continue;
+                }
+            };
+
+            for (store, gaps) in report.gaps.iter() {
+                println!("{}/{}: {} gaps in {}", report.in_domain, report.out_domain, gaps.len(), store);
+                for gap in gaps.iter() {
+                    println!("  {} - {}", gap.start.format("%Y-%m-%dT%H:%MZ"), gap.end.format("%Y-%m-%dT%H:%MZ"));
+                }
+            }
+            for (store, err) in report.errors.iter() {
+                println!("{}/{}: {} can't be scanned: {}", report.in_domain, report.out_domain, store, err);
+                failed = true;
+            }
+
+            if backfill && !report.missing().is_empty() {
+                match backfill_gaps(&config, &stores, &config.settings, &report, &EmptyFetches::new(chrono::Duration::zero())).await {
+                    Ok(fetched) => println!("{}/{}: {} intervals fetched", report.in_domain, report.out_domain, fetched),
+                    Err(err) => {
+                        error!("Error backfilling {}/{}: {:?}", report.in_domain, report.out_domain, err);
+                        failed = true;
+                    }
+                }
+            }
+        }
+
+        if failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let zones = config.zones();
+    let fetches = Semaphore::new(config.settings.get_max_concurrent_fetches());
+    let schedules = Arc::new(Schedules::default());
+    for zone in zones.iter() {
+        schedules.update(ZoneSchedule {
+            in_domain: zone.in_domain.clone(),
+            out_domain: zone.out_domain.clone(),
+            last_run: None,
+            next_run: Utc::now(),
+            coverage: None,
+        });
+    }
+
+    let run_server = config.enable_rest_api;
+    let run_update = config.enable_auto_update;
+
+    let settings_store = Arc::new(SettingsStore::new(&config_path, config.settings.clone()));
+    if config.settings_reload_interval > 0 && (run_server || run_update) {
+        tokio::spawn(
+            settings_store
+                .clone()
+                .watch(Duration::from_millis(config.settings_reload_interval)),
+        );
+    }
 
     let server_task = async {
+        let data = web::Data::new(config.clone());
+        let settings_data = web::Data::from(settings_store.clone());
+        let stores_data = web::Data::from(stores.clone());
+        let schedules_data = web::Data::from(schedules.clone());
         let server = match HttpServer::new(move || {
             App::new()
                 .wrap(middleware::Compress::default())
-                // .app_data(web::Data::new(server_client.clone()))
+                .app_data(data.clone())
+                .app_data(settings_data.clone())
+                .app_data(stores_data.clone())
+                .app_data(schedules_data.clone())
                 // register HTTP requests handlers
                 .service(health::health_check)
+                .service(health::stores_health)
                 .service(post::update_dayahead_prices)
+                .service(settings_endpoints::settings_version)
+                .service(retax::retax)
+                .service(gaps::gaps)
+                .service(schedule::schedule)
         })
-        .bind("0.0.0.0:9092")
+        .bind(&config.rest_api_address)
         {
             Ok(value) => {
-                info!("REST API started at 0.0.0.0:9092");
+                info!("REST API started at {}", config.rest_api_address);
                 value
             }
             Err(error) => panic!("Error binding to socket:{:?}", error),
@@ -86,36 +353,42 @@ async fn main() {
     };
 
     let update_task = async {
+        if zones.is_empty() {
+            warn!("No zones to update. Configure zones in the settings file or set IN_DOMAIN and OUT_DOMAIN.");
+        }
+
+        // Each zone is fetched on its own schedule so a failing zone doesn't hold back the others
+        join_all(
+            zones
+                .iter()
+                .map(|zone| schedule_zone(&config, &stores, &settings_store, &schedules, &fetches, zone)),
+        )
+        .await;
+    };
+
+    let backfill_task = async {
+        if config.gap_scan_interval == 0 {
+            return;
+        }
+
+        let empty = EmptyFetches::new(chrono::Duration::milliseconds(config.gap_scan_interval as i64));
         loop {
-            let _ = fetch_prices_for_interval(
-                &security_token,
-                &in_domain,
-                &out_domain,
-                &get_fetch_time_interval(&in_domain, &out_domain)
-                    .await
-                    .to_string(),
-            )
-            .await;
-
-            info!(
-                "Logging done, waiting for the next fetch at {} ...",
-                get_time_after_duration(interval)
-            );
-            sleep(Duration::from_millis(interval)).await;
+            backfill_zones(&config, &stores, &settings_store.current(), &zones, &empty).await;
+            sleep(Duration::from_millis(config.gap_scan_interval)).await;
         }
     };
 
     if run_server && run_update {
         info!("Running server and auto update");
-        join!(server_task, update_task);
+        join!(server_task, update_task, backfill_task);
     } else if run_server {
         info!("Running server");
         server_task.await;
     } else if run_update {
         info!("Running auto update");
-        update_task.await;
+        join!(update_task, backfill_task);
     } else {
-        warn!("Not running server or update. Enable at least one of them in .env file with ENABLE_REST_API or ENABLE_AUTO_UPDATE.");
+        warn!("Not running server or update. Enable at least one of them with enable_rest_api/enable_auto_update in the config file or ENABLE_REST_API/ENABLE_AUTO_UPDATE in .env file.");
     }
 
     // join!(server_task, update_task);
@@ -129,15 +402,17 @@ mod tests {
     async fn test_get_fetch_eet_eest() {
         dotenv().ok();
 
-        let security_token = dotenv::var("SECURITY_TOKEN").unwrap();
-
-        let in_domain = dotenv::var("IN_DOMAIN").unwrap();
-        let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
+        let config = settings::config::load_app_config(format!("configs/{}.yaml", "production")).unwrap();
+        let zone = config.default_zone().unwrap();
+        let stores = PriceStores::from_config(&config).unwrap();
 
         let _ = fetch_prices_for_interval(
-            &security_token,
-            &in_domain,
-            &out_domain,
+            &config,
+            &stores,
+            &stores.names(),
+            &config.settings,
+            &zone.in_domain,
+            &zone.out_domain,
             "2022-02-28T22:00Z/2022-03-31T21:00Z",
         )
         .await;
@@ -147,15 +422,17 @@ mod tests {
     async fn test_get_fetch_eest_eet() {
         dotenv().ok();
 
-        let security_token = dotenv::var("SECURITY_TOKEN").unwrap();
-
-        let in_domain = dotenv::var("IN_DOMAIN").unwrap();
-        let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
+        let config = settings::config::load_app_config(format!("configs/{}.yaml", "production")).unwrap();
+        let zone = config.default_zone().unwrap();
+        let stores = PriceStores::from_config(&config).unwrap();
 
         let _ = fetch_prices_for_interval(
-            &security_token,
-            &in_domain,
-            &out_domain,
+            &config,
+            &stores,
+            &stores.names(),
+            &config.settings,
+            &zone.in_domain,
+            &zone.out_domain,
             "2022-10-29T21:00Z/2022-10-30T22:00Z",
         )
         .await;
@@ -0,0 +1,588 @@
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::settings::config::ConfigError;
+use crate::settings::config_model::{ScheduleConfig, SettingsConfig, ZoneConfig};
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct InfluxDbConfig {
+    pub enabled: bool,
+    /// 1 uses InfluxQL with `database` and basic auth, 2 and 3 write line protocol to `/api/v2/write`
+    /// with `bucket` and `token`, and query with Flux (2) or SQL (3)
+    pub version: u8,
+    pub url: String,
+    pub database: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Organization, required by InfluxDB 2
+    pub org: Option<String>,
+    /// Bucket, or database in InfluxDB 3, defaults to `database`
+    pub bucket: Option<String>,
+    pub token: Option<String>,
+}
+
+impl InfluxDbConfig {
+    pub fn bucket(&self) -> &str {
+        self.bucket.as_deref().unwrap_or(&self.database)
+    }
+}
+
+impl Default for InfluxDbConfig {
+    fn default() -> Self {
+        InfluxDbConfig {
+            enabled: false,
+            version: 1,
+            url: "http://localhost:8086".to_string(),
+            database: "entsoe".to_string(),
+            username: None,
+            password: None,
+            org: None,
+            bucket: None,
+            token: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TimescaleDbConfig {
+    pub enabled: bool,
+    /// e.g. "host=localhost user=myuser dbname=electricity sslmode=require"
+    pub connection_string: String,
+    /// Maximum number of pooled connections
+    pub pool_size: usize,
+    /// PEM file of an extra trusted root certificate, e.g. the CA of a managed database
+    pub ca_certificate: Option<String>,
+    /// Apply the schema migrations at startup
+    pub migrate: bool,
+}
+
+impl Default for TimescaleDbConfig {
+    fn default() -> Self {
+        TimescaleDbConfig {
+            enabled: false,
+            connection_string: "host=localhost user=myuser password=mysecretpassword dbname=electricity".to_string(),
+            pool_size: 4,
+            ca_certificate: None,
+            migrate: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SqliteConfig {
+    pub enabled: bool,
+    /// Database file, created along with its directory if missing
+    pub path: String,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        SqliteConfig {
+            enabled: false,
+            path: "data/prices.db".to_string(),
+        }
+    }
+}
+
+/// Application configuration, loaded once at startup from the YAML file and
+/// overridden by the environment variables of the same name.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AppConfig {
+    /// ENTSO-E API token (`SECURITY_TOKEN`)
+    #[serde(default)]
+    pub security_token: String,
+    /// Milliseconds between the automatic updates (`INTERVAL`)
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// How many days to fetch at a time (`INTERVAL_DAYS`)
+    #[serde(default = "default_interval_days")]
+    pub interval_days: i64,
+    /// Expected minutes between the stored prices, gaps are looked for relative to it (`RESOLUTION_MINUTES`)
+    #[serde(default = "default_resolution_minutes")]
+    pub resolution_minutes: i64,
+    /// Milliseconds between the scans for gaps in the stored prices, 0 disables the automatic backfill (`GAP_SCAN_INTERVAL`)
+    #[serde(default = "default_gap_scan_interval")]
+    pub gap_scan_interval: u64,
+    /// `SCHEDULE_ENABLED`, `SCHEDULE_PUBLICATION_TIME`, `SCHEDULE_POLL_INTERVAL`, `SCHEDULE_RECHECK_INTERVAL`
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    /// Used if no newer data is found from the databases, e.g. "2024-01-01T00:00Z" (`START_TIME`)
+    pub start_time: Option<String>,
+    /// Default zone when no zones are configured (`IN_DOMAIN`, `OUT_DOMAIN`)
+    pub in_domain: Option<String>,
+    pub out_domain: Option<String>,
+    /// Time zone used in the log output and for the tariff rules of zones without one (`CHRONO_TIMEZONE`)
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    /// `ENABLE_REST_API`
+    #[serde(default)]
+    pub enable_rest_api: bool,
+    /// `ENABLE_AUTO_UPDATE`
+    #[serde(default)]
+    pub enable_auto_update: bool,
+    /// `REST_API_ADDRESS`
+    #[serde(default = "default_rest_api_address")]
+    pub rest_api_address: String,
+    /// Milliseconds between the checks for changes in the settings file, 0 disables reloading (`SETTINGS_RELOAD_INTERVAL`)
+    #[serde(default = "default_settings_reload_interval")]
+    pub settings_reload_interval: u64,
+    /// `INFLUXDB_ENABLED`, `INFLUXDB_VERSION`, `DATABASE_URL`, `DATABASE_NAME`, `INFLUXDB_USERNAME`, `INFLUXDB_PASSWORD`,
+    /// `INFLUXDB_ORG`, `INFLUXDB_BUCKET`, `INFLUXDB_TOKEN`
+    #[serde(default)]
+    pub influxdb: InfluxDbConfig,
+    /// `TIMESCALEDB_ENABLED`, `TIMESCALEDB_CONNECTION_STRING`, `TIMESCALEDB_POOL_SIZE`, `TIMESCALEDB_CA_CERTIFICATE`,
+    /// `TIMESCALEDB_MIGRATE`
+    #[serde(default)]
+    pub timescaledb: TimescaleDbConfig,
+    /// `SQLITE_ENABLED`, `SQLITE_PATH`
+    #[serde(default)]
+    pub sqlite: SqliteConfig,
+    /// Tax settings and zones
+    #[serde(flatten)]
+    pub settings: SettingsConfig,
+}
+
+fn default_interval() -> u64 {
+    10_000
+}
+
+fn default_interval_days() -> i64 {
+    1
+}
+
+fn default_resolution_minutes() -> i64 {
+    60
+}
+
+fn default_gap_scan_interval() -> u64 {
+    3_600_000
+}
+
+fn default_timezone() -> Tz {
+    chrono_tz::Europe::Helsinki
+}
+
+fn default_rest_api_address() -> String {
+    "0.0.0.0:9092".to_string()
+}
+
+fn default_settings_reload_interval() -> u64 {
+    10_000
+}
+
+impl AppConfig {
+    /// Configured zones with the global `start_time`, `interval_days` and `resolution_minutes` filled in,
+    /// or the `in_domain`/`out_domain` pair if no zones are configured.
+    pub fn zones(&self) -> Vec<ZoneConfig> {
+        let mut zones = self.settings.zones().to_vec();
+        if zones.is_empty() {
+            if let (Some(in_domain), Some(out_domain)) = (&self.in_domain, &self.out_domain) {
+                zones.push(ZoneConfig::new(in_domain, out_domain));
+            }
+        }
+
+        for zone in zones.iter_mut() {
+            if zone.start_time.is_none() {
+                zone.start_time = self.start_time.clone();
+            }
+            if zone.interval_days.is_none() {
+                zone.interval_days = Some(self.interval_days);
+            }
+            if zone.resolution_minutes.is_none() {
+                zone.resolution_minutes = Some(self.resolution_minutes);
+            }
+        }
+
+        zones
+    }
+
+    /// The top level `schedule` with the zone's own fields in place of the global ones.
+    pub fn zone_schedule(&self, zone: &ZoneConfig) -> ScheduleConfig {
+        match &zone.schedule {
+            Some(overrides) => self.schedule.with_overrides(overrides),
+            None => self.schedule.clone(),
+        }
+    }
+
+    /// Local time zone of the zone: its own, its area's or the global one.
+    pub fn zone_timezone(&self, zone: &ZoneConfig) -> Tz {
+        self.settings
+            .for_zone(&zone.in_domain, &zone.out_domain)
+            .timezone()
+            .unwrap_or(self.timezone)
+    }
+
+    /// The configured zone with the domains, or one with the global settings if it isn't configured.
+    pub fn zone(&self, in_domain: &str, out_domain: &str) -> ZoneConfig {
+        self.zones()
+            .into_iter()
+            .find(|zone| zone.in_domain == in_domain && zone.out_domain == out_domain)
+            .unwrap_or_else(|| {
+                let mut zone = ZoneConfig::new(in_domain, out_domain);
+                zone.start_time = self.start_time.clone();
+                zone.interval_days = Some(self.interval_days);
+                zone.resolution_minutes = Some(self.resolution_minutes);
+                zone
+            })
+    }
+
+    /// The zone used when a request doesn't specify the domains.
+    pub fn default_zone(&self) -> Option<ZoneConfig> {
+        self.zones().into_iter().next()
+    }
+
+    pub fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+        self.apply_overrides(|name| dotenv::var(name).ok())
+    }
+
+    fn apply_overrides(&mut self, var: impl Fn(&'static str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(value) = var("SECURITY_TOKEN") {
+            self.security_token = value;
+        }
+        if let Some(value) = parse_var(&var, "INTERVAL")? {
+            self.interval = value;
+        }
+        if let Some(value) = parse_var(&var, "INTERVAL_DAYS")? {
+            self.interval_days = value;
+        }
+        if let Some(value) = parse_var(&var, "RESOLUTION_MINUTES")? {
+            self.resolution_minutes = value;
+        }
+        if let Some(value) = parse_var(&var, "GAP_SCAN_INTERVAL")? {
+            self.gap_scan_interval = value;
+        }
+        if let Some(value) = parse_var(&var, "SCHEDULE_ENABLED")? {
+            self.schedule.enabled = value;
+        }
+        if let Some(value) = var("SCHEDULE_PUBLICATION_TIME") {
+            self.schedule.publication_time = value;
+        }
+        if let Some(value) = parse_var(&var, "SCHEDULE_POLL_INTERVAL")? {
+            self.schedule.poll_interval = value;
+        }
+        if let Some(value) = parse_var(&var, "SCHEDULE_RECHECK_INTERVAL")? {
+            self.schedule.recheck_interval = value;
+        }
+        if let Some(value) = var("START_TIME") {
+            self.start_time = Some(value);
+        }
+        if let Some(value) = var("IN_DOMAIN") {
+            self.in_domain = Some(value);
+        }
+        if let Some(value) = var("OUT_DOMAIN") {
+            self.out_domain = Some(value);
+        }
+        if let Some(value) = parse_var(&var, "CHRONO_TIMEZONE")? {
+            self.timezone = value;
+        }
+        if let Some(value) = parse_var(&var, "ENABLE_REST_API")? {
+            self.enable_rest_api = value;
+        }
+        if let Some(value) = parse_var(&var, "ENABLE_AUTO_UPDATE")? {
+            self.enable_auto_update = value;
+        }
+        if let Some(value) = var("REST_API_ADDRESS") {
+            self.rest_api_address = value;
+        }
+        if let Some(value) = parse_var(&var, "SETTINGS_RELOAD_INTERVAL")? {
+            self.settings_reload_interval = value;
+        }
+
+        if let Some(value) = parse_var(&var, "INFLUXDB_ENABLED")? {
+            self.influxdb.enabled = value;
+        }
+        if let Some(value) = parse_var(&var, "INFLUXDB_VERSION")? {
+            self.influxdb.version = value;
+        }
+        if let Some(value) = var("DATABASE_URL") {
+            self.influxdb.url = value;
+        }
+        if let Some(value) = var("DATABASE_NAME") {
+            self.influxdb.database = value;
+        }
+        if let Some(value) = var("INFLUXDB_USERNAME") {
+            self.influxdb.username = Some(value);
+        }
+        if let Some(value) = var("INFLUXDB_PASSWORD") {
+            self.influxdb.password = Some(value);
+        }
+        if let Some(value) = var("INFLUXDB_ORG") {
+            self.influxdb.org = Some(value);
+        }
+        if let Some(value) = var("INFLUXDB_BUCKET") {
+            self.influxdb.bucket = Some(value);
+        }
+        if let Some(value) = var("INFLUXDB_TOKEN") {
+            self.influxdb.token = Some(value);
+        }
+
+        if let Some(value) = parse_var(&var, "TIMESCALEDB_ENABLED")? {
+            self.timescaledb.enabled = value;
+        }
+        if let Some(value) = var("TIMESCALEDB_CONNECTION_STRING") {
+            self.timescaledb.connection_string = value;
+        }
+        if let Some(value) = parse_var(&var, "TIMESCALEDB_POOL_SIZE")? {
+            self.timescaledb.pool_size = value;
+        }
+        if let Some(value) = var("TIMESCALEDB_CA_CERTIFICATE") {
+            self.timescaledb.ca_certificate = Some(value);
+        }
+        if let Some(value) = parse_var(&var, "TIMESCALEDB_MIGRATE")? {
+            self.timescaledb.migrate = value;
+        }
+
+        if let Some(value) = parse_var(&var, "SQLITE_ENABLED")? {
+            self.sqlite.enabled = value;
+        }
+        if let Some(value) = var("SQLITE_PATH") {
+            self.sqlite.path = value;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the whole configuration and reports every problem found at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.security_token.is_empty() && (self.enable_auto_update || self.enable_rest_api) {
+            errors.push("security_token (SECURITY_TOKEN) is required".to_string());
+        }
+        if self.interval == 0 {
+            errors.push("interval (INTERVAL) must be greater than 0".to_string());
+        }
+        if self.interval_days <= 0 {
+            errors.push("interval_days (INTERVAL_DAYS) must be greater than 0".to_string());
+        }
+        if self.resolution_minutes <= 0 {
+            errors.push("resolution_minutes (RESOLUTION_MINUTES) must be greater than 0".to_string());
+        }
+        if self.in_domain.is_some() != self.out_domain.is_some() {
+            errors.push("in_domain (IN_DOMAIN) and out_domain (OUT_DOMAIN) must be set together".to_string());
+        }
+
+        errors.extend(self.schedule.validate(""));
+        for zone in self.settings.zones().iter().filter(|zone| zone.schedule.is_some()) {
+            errors.extend(
+                self.zone_schedule(zone)
+                    .validate(&format!(" of zone {}/{}", zone.in_domain, zone.out_domain)),
+            );
+        }
+
+        let zones = self.zones();
+        if self.enable_auto_update && zones.is_empty() {
+            errors.push("no zones configured, set zones or in_domain (IN_DOMAIN) and out_domain (OUT_DOMAIN)".to_string());
+        }
+        for zone in zones.iter() {
+            if let Some(start_time) = &zone.start_time {
+                if NaiveDateTime::parse_from_str(start_time, "%Y-%m-%dT%H:%MZ").is_err() {
+                    errors.push(format!(
+                        "start_time {:?} of zone {}/{} is not in the format YYYY-MM-DDTHH:MMZ",
+                        start_time, zone.in_domain, zone.out_domain
+                    ));
+                }
+            }
+            for (name, value) in [("in_domain", &zone.in_domain), ("out_domain", &zone.out_domain)].iter() {
+                if !api::is_eic_code(value) {
+                    errors.push(format!("{} {:?} of a zone is not an EIC code", name, value));
+                }
+            }
+            if zone.interval_days.unwrap_or(1) <= 0 {
+                errors.push(format!(
+                    "interval_days of zone {}/{} must be greater than 0",
+                    zone.in_domain, zone.out_domain
+                ));
+            }
+            if zone.resolution_minutes.unwrap_or(1) <= 0 {
+                errors.push(format!(
+                    "resolution_minutes of zone {}/{} must be greater than 0",
+                    zone.in_domain, zone.out_domain
+                ));
+            }
+        }
+
+        if self.influxdb.enabled && self.influxdb.url.is_empty() {
+            errors.push("influxdb.url (DATABASE_URL) is required when InfluxDB is enabled".to_string());
+        }
+        if self.influxdb.enabled {
+            let is_set = |value: &Option<String>| value.as_ref().is_some_and(|value| !value.is_empty());
+            match self.influxdb.version {
+                1 | 3 => {}
+                2 => {
+                    if !is_set(&self.influxdb.org) {
+                        errors.push("influxdb.org (INFLUXDB_ORG) is required for InfluxDB 2".to_string());
+                    }
+                    if !is_set(&self.influxdb.token) {
+                        errors.push("influxdb.token (INFLUXDB_TOKEN) is required for InfluxDB 2".to_string());
+                    }
+                }
+                version => errors.push(format!("influxdb.version (INFLUXDB_VERSION) must be 1, 2 or 3, not {}", version)),
+            }
+        }
+        if self.timescaledb.enabled && self.timescaledb.connection_string.is_empty() {
+            errors.push("timescaledb.connection_string (TIMESCALEDB_CONNECTION_STRING) is required when TimescaleDB is enabled".to_string());
+        }
+        if self.timescaledb.enabled && self.timescaledb.pool_size == 0 {
+            errors.push("timescaledb.pool_size (TIMESCALEDB_POOL_SIZE) must be greater than 0".to_string());
+        }
+        if self.sqlite.enabled && self.sqlite.path.is_empty() {
+            errors.push("sqlite.path (SQLITE_PATH) is required when SQLite is enabled".to_string());
+        }
+
+        if let Err(settings_errors) = self.settings.validate() {
+            errors.extend(settings_errors.iter().map(|err| err.to_string()));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+fn parse_var<T: FromStr>(
+    var: &impl Fn(&'static str) -> Option<String>,
+    name: &'static str,
+) -> Result<Option<T>, ConfigError> {
+    match var(name) {
+        Some(value) => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| ConfigError::Env { name, value }),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn config_from(yaml: &str) -> AppConfig {
+        serde_yaml::from_str(yaml).expect("Failed to parse config")
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = config_from(
+            r#"
+            interval: 60000
+            influxdb:
+              enabled: true
+            settings: []
+            "#,
+        );
+
+        let vars: HashMap<&str, &str> = [
+            ("SECURITY_TOKEN", "token"),
+            ("INTERVAL", "3600000"),
+            ("IN_DOMAIN", "10YFI-1--------U"),
+            ("OUT_DOMAIN", "10YFI-1--------U"),
+            ("CHRONO_TIMEZONE", "Europe/Stockholm"),
+            ("INFLUXDB_ENABLED", "false"),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        config
+            .apply_overrides(|name| vars.get(name).map(|value| value.to_string()))
+            .unwrap();
+
+        assert_eq!(config.security_token, "token");
+        assert_eq!(config.interval, 3_600_000);
+        assert_eq!(config.timezone, chrono_tz::Europe::Stockholm);
+        assert!(!config.influxdb.enabled);
+        assert_eq!(config.influxdb.database, "entsoe");
+        assert_eq!(config.zones().len(), 1);
+        assert_eq!(config.zones()[0].interval_days, Some(1));
+    }
+
+    #[test]
+    fn test_invalid_env_override() {
+        let mut config = config_from("settings: []");
+
+        let result = config.apply_overrides(|name| match name {
+            "INTERVAL" => Some("hourly".to_string()),
+            _ => None,
+        });
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid value \"hourly\" for environment variable INTERVAL"
+        );
+    }
+
+    #[test]
+    fn test_zone_schedule() {
+        let mut config = config_from(
+            r#"
+            schedule:
+              poll_interval: 30000
+            settings: []
+            zones:
+              - in_domain: 10YFI-1--------U
+                out_domain: 10YFI-1--------U
+              - in_domain: 10Y1001A1001A46L
+                out_domain: 10Y1001A1001A46L
+                schedule:
+                  enabled: false
+                  publication_time: "1pm"
+            "#,
+        );
+        config
+            .apply_overrides(|name| match name {
+                "SCHEDULE_RECHECK_INTERVAL" => Some("3600000".to_string()),
+                _ => None,
+            })
+            .unwrap();
+
+        let zones = config.zones();
+        let schedule = config.zone_schedule(&zones[0]);
+        assert!(schedule.enabled);
+        assert_eq!(schedule.poll_interval, 30_000);
+        assert_eq!(schedule.recheck_interval, 3_600_000);
+
+        // The zone's fields replace only those of the top level schedule
+        let schedule = config.zone_schedule(&zones[1]);
+        assert!(!schedule.enabled);
+        assert_eq!(schedule.poll_interval, 30_000);
+        assert_eq!(schedule.recheck_interval, 3_600_000);
+
+        match config.validate().unwrap_err() {
+            ConfigError::Invalid(errors) => assert_eq!(
+                errors,
+                vec!["schedule.publication_time \"1pm\" of zone 10Y1001A1001A46L/10Y1001A1001A46L is not in the format HH:MM"]
+            ),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let config = config_from(
+            r#"
+            enable_auto_update: true
+            interval_days: 0
+            resolution_minutes: 0
+            in_domain: 10YFI-1--------U
+            settings: []
+            "#,
+        );
+
+        let err = config.validate().unwrap_err();
+        match &err {
+            ConfigError::Invalid(errors) => assert_eq!(errors.len(), 5, "{}", err),
+            _ => panic!("Unexpected error {}", err),
+        }
+    }
+}
@@ -1,66 +1,301 @@
+use serde::de::DeserializeOwned;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use thiserror::Error;
-use crate::settings::config_model::SettingsConfig;
+use crate::settings::app_config::AppConfig;
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
-    #[error("Failed to deserialize config.")]
+    #[error("Failed to deserialize config: {0}")]
     Serde(#[from] serde_yaml::Error),
-    #[error("Failed to open config file")]
+    #[error("Failed to open config file: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Invalid value {value:?} for environment variable {name}")]
+    Env { name: &'static str, value: String },
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
 }
 
-pub fn load_settings(path: impl AsRef<Path>) -> Result<SettingsConfig, ConfigError> {
+pub fn load_yaml<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, ConfigError> {
     info!("Loading {}", path.as_ref().to_string_lossy());
     let mut file = File::open(path)?;
     let mut s = String::new();
     file.read_to_string(&mut s)?;
-    let t: SettingsConfig = serde_yaml::from_str(&s)?;
+    let t: T = serde_yaml::from_str(&s)?;
 
     Ok(t)
 }
 
+/// Loads the application config from the file, applies the environment variable overrides and validates the result.
+pub fn load_app_config(path: impl AsRef<Path>) -> Result<AppConfig, ConfigError> {
+    let mut config: AppConfig = load_yaml(path)?;
+
+    config.apply_env_overrides()?;
+    config.validate()?;
+
+    Ok(config)
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::{DateTime, NaiveDateTime, Utc};
+    use crate::settings::config_model::{SettingsConfig, SettingsError};
+
     use super::*;
 
     #[tokio::test]
     async fn test_load_settings() {
-        let settings = load_settings(format!("configs/{}.yaml", "test"))
+        let settings = load_yaml::<SettingsConfig>(format!("configs/{}.yaml", "test"))
             .expect("Failed to load settings file.");
 
         debug!("Settings {:#?}", settings);
 
         if let Err(err) = settings.validate() {
-            panic!("Validation error: {}", err);
+            panic!("Validation error: {:?}", err);
         }
 
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2019-12-31T22:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
-        let tax_percentage = settings.get_current_tax_percentage(dt);
+        let tax_percentage = settings.get_current_tax_percentage(dt).unwrap();
         info!("Tax percentage at {} is {:#?}%", dt, tax_percentage);
         assert_eq!(tax_percentage, 24.0);
 
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2022-11-30T22:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
-        let tax_percentage = settings.get_current_tax_percentage(dt);
+        let tax_percentage = settings.get_current_tax_percentage(dt).unwrap();
         info!("Tax percentage at {} is {:#?}%", dt, tax_percentage);
         assert_eq!(tax_percentage, 10.0);
 
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2023-04-30T20:59:59", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
-        let tax_percentage = settings.get_current_tax_percentage(dt);
+        let tax_percentage = settings.get_current_tax_percentage(dt).unwrap();
         info!("Tax percentage at {} is {:#?}%", dt, tax_percentage);
         assert_eq!(tax_percentage, 10.0);
 
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2023-04-30T21:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
-        let tax_percentage = settings.get_current_tax_percentage(dt);
+        let tax_percentage = settings.get_current_tax_percentage(dt).unwrap();
         info!("Tax percentage at {} is {:#?}%", dt, tax_percentage);
         assert_eq!(tax_percentage, 24.0);
 
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2023-05-01T00:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
-        let tax_percentage = settings.get_current_tax_percentage(dt);
+        let tax_percentage = settings.get_current_tax_percentage(dt).unwrap();
         info!("Tax percentage at {} is {:#?}%", dt, tax_percentage);
         assert_eq!(tax_percentage, 24.0);
     }
+
+    #[tokio::test]
+    async fn test_load_zone_settings() {
+        let settings = load_yaml::<SettingsConfig>(format!("configs/{}.yaml", "test_zones"))
+            .expect("Failed to load settings file.");
+
+        if let Err(err) = settings.validate() {
+            panic!("Validation error: {:?}", err);
+        }
+
+        let zones = settings.zones();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].start_time.as_deref(), Some("2024-01-01T00:00Z"));
+        assert_eq!(zones[1].interval_days, Some(2));
+        assert_eq!(settings.get_max_concurrent_fetches(), 2);
+
+        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2022-11-30T22:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        assert_eq!(settings.for_zone("10YFI-1--------U", "10YFI-1--------U").get_current_tax_percentage(dt).unwrap(), 10.0);
+        assert_eq!(settings.for_zone("10Y1001A1001A46L", "10Y1001A1001A46L").get_current_tax_percentage(dt).unwrap(), 25.0);
+        // Unknown zones fall back to the top level settings
+        assert_eq!(settings.for_zone("10YDK-1--------W", "10YDK-1--------W").get_current_tax_percentage(dt).unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_consumer_price() {
+        let settings = load_yaml::<SettingsConfig>(format!("configs/{}.yaml", "test_zones"))
+            .expect("Failed to load settings file.");
+
+        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2024-01-01T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        let price = settings.consumer_price(dt, 100.0).unwrap();
+        assert_eq!(price.spot, 10.0);
+        assert_eq!(price.margin, 0.49);
+        assert_eq!(price.electricity_tax, 2.79372);
+        assert_eq!(price.transfer_fee, 3.5);
+        assert_eq!(price.tax_percentage, 24.0);
+        assert!((price.total - (10.0 + 0.49 + 2.79372 + 3.5) * 1.24).abs() < 1e-4);
+
+        // Zone overrides replace only the components they define
+        let price = settings.for_zone("10Y1001A1001A46L", "10Y1001A1001A46L").consumer_price(dt, 100.0).unwrap();
+        assert_eq!(price.margin, 0.49);
+        assert_eq!(price.transfer_fee, 4.0);
+        assert_eq!(price.tax_percentage, 25.0);
+
+        // Components without a matching entry count as zero
+        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2021-06-01T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        let price = settings.consumer_price(dt, 50.0).unwrap();
+        assert_eq!(price.margin, 0.0);
+        assert!((price.total - 5.0 * 1.24).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_zone_settings_inheritance() {
+        let settings = load_yaml::<SettingsConfig>(format!("configs/{}.yaml", "test_zones"))
+            .expect("Failed to load settings file.");
+        settings.validate().unwrap();
+
+        let utc = |value: &str| DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        let dt = utc("2024-06-01T12:00:00");
+
+        // SE4 isn't a configured zone but gets the Swedish settings
+        let se4 = settings.for_zone("10Y1001A1001A47J", "10Y1001A1001A47J").consumer_price(dt, 100.0).unwrap();
+        assert_eq!(se4.tax_percentage, 25.0);
+        assert_eq!(se4.electricity_tax, 4.28);
+        assert_eq!(se4.margin, 0.49);
+
+        // SE3 combines the Swedish settings with its own zone overrides
+        let se3 = settings.for_zone("10Y1001A1001A46L", "10Y1001A1001A46L").consumer_price(dt, 100.0).unwrap();
+        assert_eq!(se3.electricity_tax, 4.28);
+        assert_eq!(se3.transfer_fee, 4.0);
+
+        let ee = settings.for_zone("10Y1001A1001A39I", "10Y1001A1001A39I");
+        assert_eq!(ee.get_current_tax_percentage(dt), Ok(22.0));
+        assert_eq!(ee.get_current_tax_percentage(utc("2023-06-01T12:00:00")), Ok(20.0));
+
+        // Finland still uses the top level settings
+        let fi = settings.for_zone("10YFI-1--------U", "10YFI-1--------U");
+        assert_eq!(fi.get_current_tax_percentage(dt), Ok(24.0));
+    }
+
+    #[test]
+    fn test_unknown_zone_settings_key() {
+        let settings: SettingsConfig = serde_yaml::from_str(
+            r#"
+            zone_settings:
+              XX:
+                default_tax_percentage: 20
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.validate(), Err(vec![SettingsError::UnknownZone("XX".to_string())]));
+
+        // The keys are looked up as is, so a lower case key would be silently ignored
+        let settings: SettingsConfig = serde_yaml::from_str(
+            r#"
+            zone_settings:
+              se3:
+                default_tax_percentage: 20
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.validate(), Err(vec![SettingsError::UnknownZone("se3".to_string())]));
+    }
+
+    #[test]
+    fn test_transfer_tariff_in_local_time() {
+        let settings = load_yaml::<SettingsConfig>(format!("configs/{}.yaml", "test_zones"))
+            .expect("Failed to load settings file.");
+        settings.validate().unwrap();
+        let settings = settings
+            .for_zone("10YFI-1--------U", "10YFI-1--------U")
+            .with_default_timezone(chrono_tz::Europe::Helsinki);
+
+        // 23:00 and 12:00 in Helsinki
+        let night = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2024-01-01T21:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        let day = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2024-01-02T10:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        let holiday = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2024-12-24T10:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        assert_eq!(settings.consumer_price(night, 100.0).unwrap().transfer_fee, 1.0);
+        assert_eq!(settings.consumer_price(day, 100.0).unwrap().transfer_fee, 2.0);
+        assert_eq!(settings.consumer_price(holiday, 100.0).unwrap().transfer_fee, 0.5);
+    }
+
+    #[test]
+    fn test_transfer_tariff_needs_a_timezone() {
+        let settings: SettingsConfig = serde_yaml::from_str(
+            r#"
+            default_tax_percentage: 24
+            transfer_tariffs:
+              - start_time: "2024-01-01T00:00:00"
+                default: 2.0
+                rules:
+                  - value: 1.0
+                    from: "22:00"
+                    to: "07:00"
+            "#,
+        )
+        .unwrap();
+        let night = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2024-01-01T21:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+
+        assert_eq!(settings.consumer_price(night, 100.0).unwrap_err(), SettingsError::NoTimezone(night));
+        let settings = settings.with_default_timezone(chrono_tz::Europe::Helsinki);
+        assert_eq!(settings.consumer_price(night, 100.0).unwrap().transfer_fee, 1.0);
+    }
+
+    #[test]
+    fn test_local_settings_times() {
+        let settings: SettingsConfig = serde_yaml::from_str(
+            r#"
+            settings_timezone: Europe/Helsinki
+            settings:
+              - start_time: "2024-01-01"
+                end_time: "2024-08-31"
+                tax_percentage: 24
+              - start_time: "2024-09-01T00:00:00"
+                end_time: "2025-03-30T03:59:59+03:00"
+                tax_percentage: 25.5
+              - start_time: "2025-03-30T03:00:00"
+                tax_percentage: 26
+            "#,
+        )
+        .unwrap();
+        settings.validate().unwrap();
+
+        let utc = |value: &str| DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        // Midnight in Helsinki is 21:00 UTC in the summer
+        assert_eq!(settings.get_current_tax_percentage(utc("2024-08-31T20:00:00")), Ok(24.0));
+        assert_eq!(settings.get_current_tax_percentage(utc("2024-08-31T21:00:00")), Ok(25.5));
+        // 03:00 doesn't exist on the spring DST change, the next entry starts at 04:00 EEST
+        assert_eq!(settings.get_current_tax_percentage(utc("2025-03-30T00:00:00")), Ok(25.5));
+        assert_eq!(settings.get_current_tax_percentage(utc("2025-03-30T01:00:00")), Ok(26.0));
+    }
+
+    #[test]
+    fn test_validate_names_offending_entries() {
+        let settings: SettingsConfig = serde_yaml::from_str(
+            r#"
+            settings:
+              - start_time: "2023-01-01T00:00:00"
+                end_time: "2023-06-30T23:59:59"
+                tax_percentage: 24
+              - start_time: "2023-06-01T00:00:00"
+                end_time: "2023-12-31T23:59:59"
+                tax_percentage: 25
+              - start_time: "2024-02-01T00:00:00"
+                tax_percentage: 25.5
+            margin:
+              - start_time: "2023-13-01"
+                value: 0.5
+            "#,
+        )
+        .unwrap();
+
+        let errors = settings.validate().unwrap_err();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(matches!(&errors[0], SettingsError::Overlap { first, .. } if first.starts_with("settings[0]")));
+        assert!(matches!(&errors[1], SettingsError::Gap { second, .. } if second.starts_with("settings[2]")));
+        assert_eq!(
+            errors[2].to_string(),
+            "margin[0] (start_time \"2023-13-01\"): invalid start_time \"2023-13-01\", expected RFC 3339, YYYY-MM-DDTHH:MM:SS or YYYY-MM-DD"
+        );
+    }
+
+    #[test]
+    fn test_uncovered_time_without_default() {
+        let settings: SettingsConfig = serde_yaml::from_str(
+            r#"
+            settings:
+              - start_time: "2023-01-01T00:00:00"
+                tax_percentage: 24
+            "#,
+        )
+        .unwrap();
+
+        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::parse_from_str("2022-12-31T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap(), Utc);
+        assert_eq!(settings.get_current_tax_percentage(dt), Err(SettingsError::NotCovered(dt)));
+        assert!(settings.consumer_price(dt, 100.0).is_err());
+    }
 }
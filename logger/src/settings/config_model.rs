@@ -1,71 +1,470 @@
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+
+use api::Area;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SettingConfig {
-    start_time: String,
-    end_time: Option<String>,
-    tax_percentage: f32,
-}
+use crate::settings::tariff::TariffConfig;
 
-impl SettingConfig {
-    pub fn is_match(&self, time: DateTime<Utc>) -> bool {
-        let start_time = &self.get_start_time_utc();
+/// Entry that applies from `start_time` until `end_time`, or indefinitely if there's no end time.
+///
+/// The times are either RFC 3339 with an offset, e.g. "2024-09-01T00:00:00+03:00", or local
+/// times ("2024-09-01T00:00:00") and dates ("2024-09-01") in the settings time zone.
+/// An end date includes the whole day.
+pub trait TimeBounded {
+    fn start_time(&self) -> &str;
+    fn end_time(&self) -> Option<&str>;
+
+    fn is_match(&self, time: DateTime<Utc>, timezone: &Tz) -> bool {
+        let start_time = &self.get_start_time_utc(timezone);
         if start_time.is_none() { return false }
         let start_time = start_time.unwrap();
 
         // If time is before start_time
         if time < start_time { return false }
 
-        let end_time = &self.get_end_time_utc();
-        if end_time.is_none() { return true }
+        if self.end_time().is_none() { return true }
+        let end_time = &self.get_end_time_utc(timezone);
+        if end_time.is_none() { return false }
         let end_time = end_time.unwrap();
 
         time <= end_time
     }
-    
-    fn get_start_time_utc(&self) -> Option<DateTime<Utc>> {
-        let naive_time = NaiveDateTime::parse_from_str(&self.start_time, "%Y-%m-%dT%H:%M:%S");
-        if naive_time.is_err() {
-            return None;
+
+    fn get_start_time_utc(&self, timezone: &Tz) -> Option<DateTime<Utc>> {
+        parse_config_time(self.start_time(), timezone, false)
+    }
+
+    fn get_end_time_utc(&self, timezone: &Tz) -> Option<DateTime<Utc>> {
+        parse_config_time(self.end_time()?, timezone, true)
+    }
+}
+
+/// Parses a settings time into UTC. Local times that fall into a DST gap are moved past it
+/// and repeated local times resolve to the first occurrence.
+pub fn parse_config_time(value: &str, timezone: &Tz, is_end: bool) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    if let Ok(naive_time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return local_to_utc(&naive_time, timezone);
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if is_end {
+        // Inclusive end, the last second of the day
+        let next_day = local_to_utc(&date.succ_opt()?.and_hms(0, 0, 0), timezone)?;
+        Some(next_day - chrono::Duration::seconds(1))
+    } else {
+        local_to_utc(&date.and_hms(0, 0, 0), timezone)
+    }
+}
+
+fn local_to_utc(naive_time: &NaiveDateTime, timezone: &Tz) -> Option<DateTime<Utc>> {
+    // DST gaps are at most an hour in the supported zones
+    timezone
+        .from_local_datetime(naive_time)
+        .earliest()
+        .or_else(|| timezone.from_local_datetime(&(*naive_time + chrono::Duration::hours(1))).earliest())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SettingsError {
+    #[error("{entry}: invalid {field} {value:?}, expected RFC 3339, YYYY-MM-DDTHH:MM:SS or YYYY-MM-DD")]
+    InvalidTime { entry: String, field: &'static str, value: String },
+    #[error("{first} overlaps {second} starting at {start}")]
+    Overlap { first: String, second: String, start: DateTime<Utc> },
+    #[error("Gap between {first} and {second}, nothing covers {end} - {start}")]
+    Gap { first: String, second: String, end: DateTime<Utc>, start: DateTime<Utc> },
+    #[error("{entry}: {reason}")]
+    InvalidTariff { entry: String, reason: String },
+    #[error("zone_settings.{0}: not a known zone code, country code or EIC code, codes are upper case")]
+    UnknownZone(String),
+    #[error("No tax settings cover {0} and default_tax_percentage is not set")]
+    NotCovered(DateTime<Utc>),
+    #[error("No time zone to evaluate the transfer tariffs at {0} in, resolve the settings with for_zone")]
+    NoTimezone(DateTime<Utc>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SettingConfig {
+    start_time: String,
+    end_time: Option<String>,
+    tax_percentage: f32,
+}
+
+impl TimeBounded for SettingConfig {
+    fn start_time(&self) -> &str {
+        &self.start_time
+    }
+
+    fn end_time(&self) -> Option<&str> {
+        self.end_time.as_deref()
+    }
+}
+
+/// Price component in c/kWh without VAT, e.g. the retailer's margin or the transfer fee.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceComponentConfig {
+    start_time: String,
+    end_time: Option<String>,
+    value: f32,
+}
+
+impl TimeBounded for PriceComponentConfig {
+    fn start_time(&self) -> &str {
+        &self.start_time
+    }
+
+    fn end_time(&self) -> Option<&str> {
+        self.end_time.as_deref()
+    }
+}
+
+/// Price of a kWh for the consumer at a given time. All values are in c/kWh.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct ConsumerPrice {
+    /// Day-ahead price without VAT
+    pub spot: f32,
+    /// Retailer's margin on top of the spot price without VAT
+    pub margin: f32,
+    /// Electricity excise tax without VAT
+    pub electricity_tax: f32,
+    /// Distribution system operator's transfer fee without VAT
+    pub transfer_fee: f32,
+    pub tax_percentage: f32,
+    /// Sum of the components with VAT
+    pub total: f32,
+}
+
+/// Settings that can be overridden for a zone or a country. A list replaces the inherited one as a whole.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ZoneSettingsConfig {
+    settings: Option<Vec<SettingConfig>>,
+    default_tax_percentage: Option<f32>,
+    margin: Option<Vec<PriceComponentConfig>>,
+    electricity_tax: Option<Vec<PriceComponentConfig>>,
+    transfer_fee: Option<Vec<PriceComponentConfig>>,
+    transfer_tariffs: Option<Vec<TariffConfig>>,
+    holidays: Option<Vec<NaiveDate>>,
+}
+
+/// When a zone is fetched. The day-ahead auction results are published around 12:45-13:00 CET, so the zone
+/// is polled every `poll_interval` from `publication_time` until the next day's prices are stored, and otherwise
+/// rechecked every `recheck_interval` for corrections. Disabled, the zone is fetched every `INTERVAL`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    pub enabled: bool,
+    /// Time of day the results are expected from, e.g. "12:45"
+    pub publication_time: String,
+    /// Time zone of `publication_time`
+    pub publication_timezone: Tz,
+    /// How long after `publication_time` to keep polling
+    pub window_minutes: i64,
+    /// Milliseconds between the fetches while waiting for the next day's prices
+    pub poll_interval: u64,
+    /// Milliseconds between the fetches otherwise
+    pub recheck_interval: u64,
+}
+
+/// Fields of the top level `schedule` overridden for a zone, the others are inherited.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ZoneScheduleConfig {
+    pub enabled: Option<bool>,
+    pub publication_time: Option<String>,
+    pub publication_timezone: Option<Tz>,
+    pub window_minutes: Option<i64>,
+    pub poll_interval: Option<u64>,
+    pub recheck_interval: Option<u64>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            enabled: true,
+            publication_time: "12:45".to_string(),
+            publication_timezone: chrono_tz::Europe::Brussels,
+            window_minutes: 180,
+            poll_interval: 60_000,
+            recheck_interval: 21_600_000,
         }
+    }
+}
 
-        Some(Utc.from_utc_datetime(&naive_time.unwrap()))
+impl ScheduleConfig {
+    pub fn publication_time(&self) -> Option<NaiveTime> {
+        NaiveTime::parse_from_str(&self.publication_time, "%H:%M").ok()
     }
 
-    fn get_end_time_utc(&self) -> Option<DateTime<Utc>> {
-        if self.end_time.is_none() {
-            return None;
+    /// This schedule with the fields set for a zone replaced.
+    pub fn with_overrides(&self, overrides: &ZoneScheduleConfig) -> ScheduleConfig {
+        let mut schedule = self.clone();
+        if let Some(enabled) = overrides.enabled {
+            schedule.enabled = enabled;
+        }
+        if let Some(publication_time) = &overrides.publication_time {
+            schedule.publication_time = publication_time.clone();
+        }
+        if let Some(publication_timezone) = overrides.publication_timezone {
+            schedule.publication_timezone = publication_timezone;
         }
+        if let Some(window_minutes) = overrides.window_minutes {
+            schedule.window_minutes = window_minutes;
+        }
+        if let Some(poll_interval) = overrides.poll_interval {
+            schedule.poll_interval = poll_interval;
+        }
+        if let Some(recheck_interval) = overrides.recheck_interval {
+            schedule.recheck_interval = recheck_interval;
+        }
+
+        schedule
+    }
+
+    /// Problems with the values, `context` tells which schedule, e.g. " of zone 10YFI-1--------U/10YFI-1--------U".
+    pub fn validate(&self, context: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.publication_time().is_none() {
+            errors.push(format!(
+                "schedule.publication_time {:?}{} is not in the format HH:MM",
+                self.publication_time, context
+            ));
+        }
+        if self.window_minutes < 0 {
+            errors.push(format!("schedule.window_minutes{} must not be negative", context));
+        }
+        if self.poll_interval == 0 {
+            errors.push(format!("schedule.poll_interval{} must be greater than 0", context));
+        }
+        if self.recheck_interval == 0 {
+            errors.push(format!("schedule.recheck_interval{} must be greater than 0", context));
+        }
+
+        errors
+    }
+}
 
-        let naive_time = NaiveDateTime::parse_from_str(&self.end_time.as_ref().unwrap(), "%Y-%m-%dT%H:%M:%S");
-        if naive_time.is_err() {
-            panic!("Failed to parse end time {}", self.end_time.as_ref().unwrap())
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ZoneConfig {
+    pub in_domain: String,
+    pub out_domain: String,
+    /// Overrides `START_TIME` for this zone, e.g. "2024-01-01T00:00Z"
+    pub start_time: Option<String>,
+    /// Overrides `INTERVAL_DAYS` for this zone
+    pub interval_days: Option<i64>,
+    /// Overrides `RESOLUTION_MINUTES` for this zone
+    pub resolution_minutes: Option<i64>,
+    /// Overrides fields of the top level `schedule` for this zone
+    pub schedule: Option<ZoneScheduleConfig>,
+    /// Time zone the tariff rules are evaluated in, the bidding zone's own time zone if not set
+    pub timezone: Option<Tz>,
+    /// Tax settings and price components for this zone, inherited from `zone_settings` and the top level if not set
+    #[serde(flatten)]
+    overrides: ZoneSettingsConfig,
+}
+
+impl ZoneConfig {
+    pub fn new(in_domain: &str, out_domain: &str) -> ZoneConfig {
+        ZoneConfig {
+            in_domain: in_domain.to_string(),
+            out_domain: out_domain.to_string(),
+            start_time: None,
+            interval_days: None,
+            resolution_minutes: None,
+            schedule: None,
+            timezone: None,
+            overrides: ZoneSettingsConfig::default(),
         }
+    }
 
-        Some(Utc.from_utc_datetime(&naive_time.unwrap()))
+    pub fn is_match(&self, in_domain: &str, out_domain: &str) -> bool {
+        self.in_domain == in_domain && self.out_domain == out_domain
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SettingsConfig {
+    #[serde(default)]
     settings: Vec<SettingConfig>,
+    /// Tax percentage for the times the settings don't cover. If not set, those times are an error.
+    default_tax_percentage: Option<f32>,
+    #[serde(default)]
+    margin: Vec<PriceComponentConfig>,
+    #[serde(default)]
+    electricity_tax: Vec<PriceComponentConfig>,
+    #[serde(default)]
+    transfer_fee: Vec<PriceComponentConfig>,
+    /// Time-of-use transfer tariffs, used instead of `transfer_fee` when one matches
+    #[serde(default)]
+    transfer_tariffs: Vec<TariffConfig>,
+    /// Public holidays for the tariff rules, e.g. "2024-12-24"
+    #[serde(default)]
+    holidays: Vec<NaiveDate>,
+    /// Time zone of the settings times without an offset, UTC if not set
+    settings_timezone: Option<Tz>,
+    /// Overrides by country (`SE`), zone code (`SE3`) or EIC code, applied in that order
+    #[serde(default)]
+    zone_settings: BTreeMap<String, ZoneSettingsConfig>,
+    #[serde(default)]
+    zones: Vec<ZoneConfig>,
+    max_concurrent_fetches: Option<usize>,
+    /// Time zone of the zone these settings were resolved for, see `for_zone`
+    #[serde(skip)]
+    timezone: Option<Tz>,
 }
 
 impl SettingsConfig {
-    pub fn get_current_tax_percentage(&self, time: DateTime<Utc>) -> f32 {
-        let setting = self.get_setting(time);
-        if setting.is_none() {
-            return 24.0;
+    pub fn zones(&self) -> &[ZoneConfig] {
+        &self.zones
+    }
+
+    /// Time zone of the zone's local day, set by `for_zone` and `with_default_timezone`.
+    pub fn timezone(&self) -> Option<Tz> {
+        self.timezone
+    }
+
+    pub fn get_max_concurrent_fetches(&self) -> usize {
+        self.max_concurrent_fetches.unwrap_or(4).max(1)
+    }
+
+    /// Tax settings and price components that apply to the given domains. The top level settings are
+    /// overridden by `zone_settings` from the least to the most specific key and then by the matching zone.
+    pub fn for_zone(&self, in_domain: &str, out_domain: &str) -> SettingsConfig {
+        let zone = self.zones
+            .iter()
+            .find(|zone| zone.is_match(in_domain, out_domain));
+        let area = Area::find(in_domain);
+
+        let mut resolved = SettingsConfig {
+            settings: self.settings.clone(),
+            default_tax_percentage: self.default_tax_percentage,
+            margin: self.margin.clone(),
+            electricity_tax: self.electricity_tax.clone(),
+            transfer_fee: self.transfer_fee.clone(),
+            transfer_tariffs: self.transfer_tariffs.clone(),
+            holidays: self.holidays.clone(),
+            settings_timezone: self.settings_timezone,
+            zone_settings: BTreeMap::new(),
+            zones: Vec::new(),
+            max_concurrent_fetches: self.max_concurrent_fetches,
+            timezone: zone
+                .and_then(|zone| zone.timezone)
+                .or_else(|| area.map(|area| area.timezone))
+                .or(self.timezone),
+        };
+
+        let mut keys = Vec::new();
+        if let Some(area) = area {
+            keys.push(area.country());
+            keys.push(area.code);
+        }
+        keys.push(in_domain);
+        keys.dedup();
+        for key in keys {
+            if let Some(overrides) = self.zone_settings.get(key) {
+                resolved.apply(overrides);
+            }
+        }
+
+        if let Some(zone) = zone {
+            resolved.apply(&zone.overrides);
+        }
+
+        resolved
+    }
+
+    fn apply(&mut self, overrides: &ZoneSettingsConfig) {
+        if let Some(settings) = &overrides.settings {
+            self.settings = settings.clone();
+        }
+        if let Some(default_tax_percentage) = overrides.default_tax_percentage {
+            self.default_tax_percentage = Some(default_tax_percentage);
+        }
+        if let Some(margin) = &overrides.margin {
+            self.margin = margin.clone();
+        }
+        if let Some(electricity_tax) = &overrides.electricity_tax {
+            self.electricity_tax = electricity_tax.clone();
+        }
+        if let Some(transfer_fee) = &overrides.transfer_fee {
+            self.transfer_fee = transfer_fee.clone();
         }
+        if let Some(transfer_tariffs) = &overrides.transfer_tariffs {
+            self.transfer_tariffs = transfer_tariffs.clone();
+        }
+        if let Some(holidays) = &overrides.holidays {
+            self.holidays = holidays.clone();
+        }
+    }
 
-        setting.unwrap().tax_percentage
+    /// Sets the time zone used for the tariff rules if the zone doesn't define one.
+    pub fn with_default_timezone(mut self, timezone: Tz) -> SettingsConfig {
+        self.timezone.get_or_insert(timezone);
+        self
+    }
+
+    /// Consumer price at the given time for a day-ahead price in EUR/MWh.
+    /// Components without a matching entry are left out of the total.
+    pub fn consumer_price(&self, time: DateTime<Utc>, spot: f32) -> Result<ConsumerPrice, SettingsError> {
+        let spot = spot / 10.0;
+        let margin = self.get_component(&self.margin, time);
+        let electricity_tax = self.get_component(&self.electricity_tax, time);
+        let transfer_fee = self.get_transfer_fee(time)?;
+        let tax_percentage = self.get_current_tax_percentage(time)?;
+
+        Ok(ConsumerPrice {
+            spot,
+            margin,
+            electricity_tax,
+            transfer_fee,
+            tax_percentage,
+            total: (spot + margin + electricity_tax + transfer_fee) * (1.0 + tax_percentage / 100.0),
+        })
+    }
+
+    /// The tariff rules are in the zone's local time, so they need the time zone `for_zone` or
+    /// `with_default_timezone` resolved.
+    fn get_transfer_fee(&self, time: DateTime<Utc>) -> Result<f32, SettingsError> {
+        match self.find_match(&self.transfer_tariffs, time) {
+            Some(tariff) => {
+                let timezone = self.timezone.ok_or(SettingsError::NoTimezone(time))?;
+                Ok(tariff.value_at(&time.with_timezone(&timezone), &self.holidays))
+            }
+            None => Ok(self.get_component(&self.transfer_fee, time)),
+        }
+    }
+
+    fn get_component(&self, components: &[PriceComponentConfig], time: DateTime<Utc>) -> f32 {
+        self.find_match(components, time)
+            .map(|component| component.value)
+            .unwrap_or(0.0)
+    }
+
+    pub fn get_current_tax_percentage(&self, time: DateTime<Utc>) -> Result<f32, SettingsError> {
+        match self.get_setting(time) {
+            Some(setting) => Ok(setting.tax_percentage),
+            None => self.default_tax_percentage.ok_or(SettingsError::NotCovered(time)),
+        }
     }
 
     pub fn get_setting(&self, time: DateTime<Utc>) -> Option<&SettingConfig> {
-        let matches: Vec<&SettingConfig> = self.settings
+        self.find_match(&self.settings, time)
+    }
+
+    fn settings_timezone(&self) -> Tz {
+        self.settings_timezone.unwrap_or(chrono_tz::UTC)
+    }
+
+    fn find_match<'a, T: TimeBounded>(&self, entries: &'a [T], time: DateTime<Utc>) -> Option<&'a T> {
+        let timezone = self.settings_timezone();
+        let matches: Vec<&T> = entries
             .iter()
-            .filter(|voc| voc.is_match(time))
+            .filter(|voc| voc.is_match(time, &timezone))
             .collect();
 
         // If just one match, return it
@@ -78,28 +477,123 @@ impl SettingsConfig {
         None
     }
 
-    pub fn validate(&self) -> Result<(), &'static str> {
-        self.validate_times()?;
+    /// Checks every list of time-bounded entries and reports all the problems found.
+    /// Gaps are only errors in the tax settings, price components without an entry count as zero.
+    pub fn validate(&self) -> Result<(), Vec<SettingsError>> {
+        let mut errors = Vec::new();
+
+        self.validate_times("settings", &self.settings, true, &mut errors);
+        self.validate_times("margin", &self.margin, false, &mut errors);
+        self.validate_times("electricity_tax", &self.electricity_tax, false, &mut errors);
+        self.validate_times("transfer_fee", &self.transfer_fee, false, &mut errors);
+        self.validate_tariffs("transfer_tariffs", &self.transfer_tariffs, &mut errors);
+
+        for (key, overrides) in self.zone_settings.iter() {
+            // `for_zone` looks the keys up as is, so a key in the wrong case would never apply
+            let is_known = api::AREAS
+                .iter()
+                .any(|area| area.code == key || area.eic == key || area.country() == key);
+            if !is_known {
+                errors.push(SettingsError::UnknownZone(key.clone()));
+            }
+            self.validate_overrides(&format!("zone_settings.{}", key), overrides, &mut errors);
+        }
 
-        Ok(())
+        for (index, zone) in self.zones.iter().enumerate() {
+            self.validate_overrides(&format!("zones[{}]", index), &zone.overrides, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
-    fn validate_times(&self) -> Result<(), &'static str> {
-        let mut settings = self.settings.clone();
-        settings.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    fn validate_overrides(&self, name: &str, overrides: &ZoneSettingsConfig, errors: &mut Vec<SettingsError>) {
+        let list = |list: &str| format!("{}.{}", name, list);
+        if let Some(settings) = &overrides.settings {
+            self.validate_times(&list("settings"), settings, true, errors);
+        }
+        if let Some(margin) = &overrides.margin {
+            self.validate_times(&list("margin"), margin, false, errors);
+        }
+        if let Some(electricity_tax) = &overrides.electricity_tax {
+            self.validate_times(&list("electricity_tax"), electricity_tax, false, errors);
+        }
+        if let Some(transfer_fee) = &overrides.transfer_fee {
+            self.validate_times(&list("transfer_fee"), transfer_fee, false, errors);
+        }
+        if let Some(tariffs) = &overrides.transfer_tariffs {
+            self.validate_tariffs(&list("transfer_tariffs"), tariffs, errors);
+        }
+    }
 
-        for windows in settings.windows(2) {
-            let first = &windows[0];
-            let second = &windows[1];
+    fn validate_tariffs(&self, list: &str, tariffs: &[TariffConfig], errors: &mut Vec<SettingsError>) {
+        for (index, tariff) in tariffs.iter().enumerate() {
+            if let Err(reason) = tariff.validate() {
+                errors.push(SettingsError::InvalidTariff { entry: entry_name(list, index, tariff), reason });
+            }
+        }
+
+        self.validate_times(list, tariffs, false, errors);
+    }
 
-            let start_time = second.get_start_time_utc().unwrap();
-            let end_time = first.get_end_time_utc().unwrap();
+    fn validate_times<T: TimeBounded>(&self, list: &str, entries: &[T], check_gaps: bool, errors: &mut Vec<SettingsError>) {
+        let timezone = self.settings_timezone();
 
-            if end_time >= start_time {
-                return Err("Overlapping contracts detected");
+        let mut ranges = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let start_time = entry.get_start_time_utc(&timezone);
+            if start_time.is_none() {
+                errors.push(SettingsError::InvalidTime {
+                    entry: entry_name(list, index, entry),
+                    field: "start_time",
+                    value: entry.start_time().to_string(),
+                });
+            }
+            let end_time = entry.get_end_time_utc(&timezone);
+            if let (Some(value), None) = (entry.end_time(), end_time) {
+                errors.push(SettingsError::InvalidTime {
+                    entry: entry_name(list, index, entry),
+                    field: "end_time",
+                    value: value.to_string(),
+                });
+                continue;
+            }
+            if let Some(start_time) = start_time {
+                ranges.push((start_time, end_time, entry_name(list, index, entry)));
             }
         }
 
-        Ok(())
+        ranges.sort_by_key(|(start_time, _, _)| *start_time);
+
+        for windows in ranges.windows(2) {
+            let (_, end_time, first) = &windows[0];
+            let (start_time, _, second) = &windows[1];
+
+            match end_time {
+                // End times are inclusive, so the next entry should start a second later
+                Some(end_time) if *end_time < *start_time => {
+                    if check_gaps && *end_time + chrono::Duration::seconds(1) < *start_time {
+                        errors.push(SettingsError::Gap {
+                            first: first.clone(),
+                            second: second.clone(),
+                            end: *end_time,
+                            start: *start_time,
+                        });
+                    }
+                }
+                _ => errors.push(SettingsError::Overlap {
+                    first: first.clone(),
+                    second: second.clone(),
+                    start: *start_time,
+                }),
+            }
+        }
     }
 }
+
+fn entry_name<T: TimeBounded>(list: &str, index: usize, entry: &T) -> String {
+    format!("{}[{}] (start_time {:?})", list, index, entry.start_time())
+}
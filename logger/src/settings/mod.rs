@@ -1,2 +1,5 @@
+pub mod app_config;
 pub mod config;
 pub mod config_model;
+pub mod tariff;
+pub mod watcher;
\ No newline at end of file
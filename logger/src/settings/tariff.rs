@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::config_model::TimeBounded;
+
+/// Time-of-use transfer tariff, e.g. separate day and night fees.
+/// The first matching rule sets the fee, `default` applies when no rule matches.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TariffConfig {
+    start_time: String,
+    end_time: Option<String>,
+    /// Fee in c/kWh without VAT when none of the rules match
+    default: f32,
+    #[serde(default)]
+    rules: Vec<TariffRule>,
+}
+
+impl TimeBounded for TariffConfig {
+    fn start_time(&self) -> &str {
+        &self.start_time
+    }
+
+    fn end_time(&self) -> Option<&str> {
+        self.end_time.as_deref()
+    }
+}
+
+/// Rule evaluated in the zone's local time. Conditions that are left out match any time.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TariffRule {
+    /// Fee in c/kWh without VAT
+    value: f32,
+    /// Start of the daily window, e.g. "07:00"
+    from: Option<String>,
+    /// End of the daily window (exclusive), e.g. "22:00". The window may wrap over midnight.
+    to: Option<String>,
+    /// e.g. ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat"]
+    weekdays: Option<Vec<String>>,
+    /// Months 1-12
+    months: Option<Vec<u32>>,
+    /// true matches only public holidays, false only other days
+    holiday: Option<bool>,
+}
+
+impl TariffConfig {
+    pub fn value_at(&self, time: &DateTime<Tz>, holidays: &[NaiveDate]) -> f32 {
+        self.rules
+            .iter()
+            .find(|rule| rule.is_match(time, holidays))
+            .map(|rule| rule.value)
+            .unwrap_or(self.default)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            rule.validate().map_err(|reason| format!("rules[{}]: {}", index, reason))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TariffRule {
+    fn is_match(&self, time: &DateTime<Tz>, holidays: &[NaiveDate]) -> bool {
+        if let Some(months) = &self.months {
+            if !months.contains(&time.month()) {
+                return false;
+            }
+        }
+
+        if let Some(weekdays) = &self.weekdays {
+            if !weekdays.iter().any(|weekday| Weekday::from_str(weekday).ok() == Some(time.weekday())) {
+                return false;
+            }
+        }
+
+        if let Some(holiday) = self.holiday {
+            if holidays.contains(&time.date().naive_local()) != holiday {
+                return false;
+            }
+        }
+
+        let time_of_day = NaiveTime::from_hms(time.hour(), time.minute(), 0);
+        let from = self.from.as_deref().and_then(parse_time_of_day);
+        let to = self.to.as_deref().and_then(parse_time_of_day);
+        match (from, to) {
+            (Some(from), Some(to)) if from <= to => from <= time_of_day && time_of_day < to,
+            (Some(from), Some(to)) => from <= time_of_day || time_of_day < to,
+            (Some(from), None) => from <= time_of_day,
+            (None, Some(to)) => time_of_day < to,
+            (None, None) => true,
+        }
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        for time in [&self.from, &self.to].iter().copied().flatten() {
+            if parse_time_of_day(time).is_none() {
+                return Err("from and to must be in the format HH:MM");
+            }
+        }
+
+        for weekday in self.weekdays.iter().flatten() {
+            if Weekday::from_str(weekday).is_err() {
+                return Err("unknown weekday");
+            }
+        }
+
+        if self.months.iter().flatten().any(|month| !(1..=12).contains(month)) {
+            return Err("months must be between 1 and 12");
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_time_of_day(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Helsinki;
+
+    use super::*;
+
+    fn seasonal_tariff() -> TariffConfig {
+        serde_yaml::from_str(
+            r#"
+            start_time: "2024-01-01T00:00:00"
+            default: 1.5
+            rules:
+              - value: 0.0
+                holiday: true
+              - value: 3.5
+                from: "07:00"
+                to: "22:00"
+                weekdays: [Mon, Tue, Wed, Thu, Fri, Sat]
+                months: [11, 12, 1, 2, 3]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_seasonal_tariff() {
+        let tariff = seasonal_tariff();
+        tariff.validate().unwrap();
+        let holidays = [NaiveDate::from_ymd(2024, 1, 6)];
+
+        // Winter weekday daytime
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 1, 8).and_hms(7, 0, 0), &holidays), 3.5);
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 1, 8).and_hms(22, 0, 0), &holidays), 1.5);
+        // Sunday
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 1, 7).and_hms(12, 0, 0), &holidays), 1.5);
+        // Summer
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 7, 8).and_hms(12, 0, 0), &holidays), 1.5);
+        // Public holiday on a Saturday
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 1, 6).and_hms(12, 0, 0), &holidays), 0.0);
+    }
+
+    #[test]
+    fn test_night_window_wraps_over_midnight() {
+        let tariff: TariffConfig = serde_yaml::from_str(
+            r#"
+            start_time: "2024-01-01T00:00:00"
+            default: 4.0
+            rules:
+              - value: 2.0
+                from: "22:00"
+                to: "07:00"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 3, 1).and_hms(23, 0, 0), &[]), 2.0);
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 3, 1).and_hms(6, 59, 0), &[]), 2.0);
+        assert_eq!(tariff.value_at(&Helsinki.ymd(2024, 3, 1).and_hms(7, 0, 0), &[]), 4.0);
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::time::sleep;
+
+use crate::settings::config::{load_yaml, ConfigError};
+use crate::settings::config_model::SettingsConfig;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsVersion {
+    pub version: u64,
+    pub loaded_at: DateTime<Utc>,
+    /// Why the latest edit of the file was rejected, if it was
+    pub last_error: Option<String>,
+}
+
+struct SettingsState {
+    settings: Arc<SettingsConfig>,
+    version: SettingsVersion,
+    modified: Option<SystemTime>,
+}
+
+/// Holds the active tax settings and swaps in new ones when the settings file changes.
+/// A file that fails to load or validate never replaces the active settings.
+pub struct SettingsStore {
+    path: PathBuf,
+    state: RwLock<SettingsState>,
+}
+
+impl SettingsStore {
+    pub fn new(path: impl AsRef<Path>, settings: SettingsConfig) -> SettingsStore {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+
+        SettingsStore {
+            path,
+            state: RwLock::new(SettingsState {
+                settings: Arc::new(settings),
+                version: SettingsVersion {
+                    version: 1,
+                    loaded_at: Utc::now(),
+                    last_error: None,
+                },
+                modified,
+            }),
+        }
+    }
+
+    pub fn current(&self) -> Arc<SettingsConfig> {
+        self.state.read().unwrap().settings.clone()
+    }
+
+    pub fn version(&self) -> SettingsVersion {
+        self.state.read().unwrap().version.clone()
+    }
+
+    /// Reloads the settings if the file has been modified since the last check.
+    /// Returns the new version number if the settings were swapped.
+    pub fn reload_if_changed(&self) -> Result<Option<u64>, ConfigError> {
+        let modified = modified_time(&self.path);
+        if modified == self.state.read().unwrap().modified {
+            return Ok(None);
+        }
+
+        let result = load_yaml::<SettingsConfig>(&self.path).and_then(|settings| {
+            settings
+                .validate()
+                .map_err(|errors| ConfigError::Invalid(errors.iter().map(|err| err.to_string()).collect()))?;
+            Ok(settings)
+        });
+
+        let mut state = self.state.write().unwrap();
+        // Remember the rejected file too so the same error isn't reported on every check
+        state.modified = modified;
+        match result {
+            Ok(settings) => {
+                state.settings = Arc::new(settings);
+                state.version = SettingsVersion {
+                    version: state.version.version + 1,
+                    loaded_at: Utc::now(),
+                    last_error: None,
+                };
+                Ok(Some(state.version.version))
+            }
+            Err(err) => {
+                state.version.last_error = Some(err.to_string());
+                Err(err)
+            }
+        }
+    }
+
+    /// Polls the settings file for changes. Polling is used instead of file system events
+    /// because those are unreliable for files mounted into containers.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        info!("Watching {} for settings changes", self.path.to_string_lossy());
+        loop {
+            sleep(interval).await;
+
+            match self.reload_if_changed() {
+                Ok(Some(version)) => info!("Settings reloaded, active version is {}", version),
+                Ok(None) => {}
+                Err(err) => error!(
+                    "Failed to reload the settings, keeping version {}: {}",
+                    self.version().version,
+                    err
+                ),
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn write_settings(path: &Path, tax_percentage: &str, modified_secs: u64) {
+        fs::write(
+            path,
+            format!("settings:\n  - start_time: \"2020-01-01T00:00:00\"\n    tax_percentage: {}\n", tax_percentage),
+        )
+        .unwrap();
+        // Set the modification time explicitly, consecutive writes can get the same one
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified_secs)))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_reload_keeps_previous_settings_on_error() {
+        let path = std::env::temp_dir().join(format!("entsoe-logger-watcher-{}.yaml", std::process::id()));
+        write_settings(&path, "24", 1);
+
+        let store = SettingsStore::new(&path, load_yaml(&path).unwrap());
+        let time = Utc.ymd(2023, 1, 1).and_hms(0, 0, 0);
+        assert_eq!(store.reload_if_changed().unwrap(), None);
+        assert_eq!(store.current().get_current_tax_percentage(time).unwrap(), 24.0);
+
+        write_settings(&path, "25.5", 2);
+        assert_eq!(store.reload_if_changed().unwrap(), Some(2));
+        assert_eq!(store.current().get_current_tax_percentage(time).unwrap(), 25.5);
+
+        write_settings(&path, "not a number", 3);
+        assert!(store.reload_if_changed().is_err());
+        assert_eq!(store.current().get_current_tax_percentage(time).unwrap(), 25.5);
+        assert_eq!(store.version().version, 2);
+        assert!(store.version().last_error.is_some());
+
+        // The broken file is only reported once
+        assert_eq!(store.reload_if_changed().unwrap(), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
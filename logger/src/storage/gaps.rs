@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::settings::config_model::ZoneConfig;
+use crate::storage::price_store::PriceStores;
+
+/// Missing prices in `[start, end)`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Gap {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GapReport {
+    pub in_domain: String,
+    pub out_domain: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub resolution_minutes: i64,
+    /// Gaps by store
+    pub gaps: BTreeMap<&'static str, Vec<Gap>>,
+    /// Stores that couldn't be scanned
+    pub errors: BTreeMap<&'static str, String>,
+}
+
+impl GapReport {
+    /// Gaps of all the stores, overlapping ones merged, with the stores missing prices in them.
+    /// Each range is fetched once and written only to those stores.
+    pub fn missing(&self) -> Vec<(Gap, Vec<&'static str>)> {
+        let mut gaps: Vec<(Gap, &'static str)> = self
+            .gaps
+            .iter()
+            .flat_map(|(store, gaps)| gaps.iter().map(move |gap| (*gap, *store)))
+            .collect();
+        gaps.sort_by_key(|(gap, _)| gap.start);
+
+        let mut merged: Vec<(Gap, Vec<&'static str>)> = Vec::new();
+        for (gap, store) in gaps {
+            match merged.last_mut() {
+                Some((last, stores)) if gap.start <= last.end => {
+                    last.end = last.end.max(gap.end);
+                    if !stores.contains(&store) {
+                        stores.push(store);
+                    }
+                }
+                _ => merged.push((gap, vec![store])),
+            }
+        }
+
+        merged
+    }
+
+    /// Leaves out the store's gaps from `cursor` on, the regular fetch continues from there.
+    pub fn truncate(&mut self, store: &str, cursor: DateTime<Utc>) {
+        if let Some(gaps) = self.gaps.get_mut(store) {
+            gaps.retain(|gap| gap.start < cursor);
+            for gap in gaps.iter_mut() {
+                gap.end = gap.end.min(cursor);
+            }
+        }
+    }
+}
+
+/// Looks for missing prices of the zone in every store, from `start`, the zone's `start_time` or the earliest
+/// stored price to `end` or the latest one. Prices not published yet aren't gaps, the regular fetch gets them.
+/// Stores that can't be reached are reported as errors, the others are still scanned.
+pub async fn scan_gaps(
+    stores: &PriceStores,
+    zone: &ZoneConfig,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<GapReport, anyhow::Error> {
+    let (in_domain, out_domain) = (zone.in_domain.as_str(), zone.out_domain.as_str());
+    let resolution_minutes = zone.resolution_minutes.unwrap_or(60);
+    let resolution = Duration::minutes(resolution_minutes);
+
+    let start = start.or_else(|| {
+        zone.start_time
+            .as_deref()
+            .and_then(|start_time| NaiveDateTime::parse_from_str(start_time, "%Y-%m-%dT%H:%MZ").ok())
+            .map(|start_time| DateTime::from_utc(start_time, Utc))
+    });
+    let mut errors = BTreeMap::new();
+    let latest_times = join_all(stores.iter().map(|store| store.latest_time(in_domain, out_domain))).await;
+    let mut latest = None;
+    for (store, latest_time) in stores.iter().zip(latest_times) {
+        match latest_time {
+            Ok(latest_time) => latest = latest.max(latest_time),
+            Err(err) => {
+                errors.insert(store.name(), err.to_string());
+            }
+        }
+    }
+    let end = end.unwrap_or_else(|| latest.map_or_else(Utc::now, |latest| latest + resolution));
+
+    let reachable: Vec<_> = stores.iter().filter(|store| !errors.contains_key(store.name())).collect();
+    let mut stored_times = Vec::new();
+    for store in reachable {
+        match store
+            .read_range(in_domain, out_domain, start.unwrap_or_else(entsoe_start), end)
+            .await
+        {
+            Ok(stored) => stored_times.push((store.name(), stored.iter().map(|price| price.time).collect::<Vec<_>>())),
+            Err(err) => {
+                errors.insert(store.name(), err.to_string());
+            }
+        }
+    }
+    for (store, err) in errors.iter() {
+        error!("Error looking for gaps of {}/{} in {}: {}", in_domain, out_domain, store, err);
+    }
+
+    // Without a start time, nothing before the first stored price is missing
+    let start = start
+        .or_else(|| stored_times.iter().filter_map(|(_, times)| times.first()).min().copied())
+        .unwrap_or(end);
+
+    Ok(GapReport {
+        in_domain: in_domain.to_string(),
+        out_domain: out_domain.to_string(),
+        start,
+        end,
+        resolution_minutes,
+        gaps: stored_times
+            .into_iter()
+            .map(|(store, times)| (store, find_gaps(&times, start, end, resolution)))
+            .collect(),
+        errors,
+    })
+}
+
+/// The transparency platform has prices since 2015.
+fn entsoe_start() -> DateTime<Utc> {
+    Utc.ymd(2015, 1, 1).and_hms(0, 0, 0)
+}
+
+/// Ranges in `[start, end)` with no price within `resolution` of the previous one. `times` are ordered.
+pub fn find_gaps(times: &[DateTime<Utc>], start: DateTime<Utc>, end: DateTime<Utc>, resolution: Duration) -> Vec<Gap> {
+    let mut gaps = Vec::new();
+    let mut expected = start;
+
+    for time in times.iter().copied().filter(|time| *time >= start && *time < end) {
+        if time > expected {
+            gaps.push(Gap { start: expected, end: time });
+        }
+        expected = expected.max(time + resolution);
+    }
+    if expected < end {
+        gaps.push(Gap { start: expected, end });
+    }
+
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hour(hour: i64) -> DateTime<Utc> {
+        Utc.ymd(2024, 1, 1).and_hms(0, 0, 0) + Duration::hours(hour)
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let times: Vec<DateTime<Utc>> = [0, 1, 2, 5, 6, 9].iter().map(|h| hour(*h)).collect();
+
+        assert_eq!(
+            find_gaps(&times, hour(0), hour(12), Duration::hours(1)),
+            vec![
+                Gap { start: hour(3), end: hour(5) },
+                Gap { start: hour(7), end: hour(9) },
+                Gap { start: hour(10), end: hour(12) },
+            ]
+        );
+        assert_eq!(find_gaps(&times, hour(0), hour(10), Duration::hours(1)).len(), 2);
+        assert_eq!(
+            find_gaps(&[], hour(0), hour(10), Duration::hours(1)),
+            vec![Gap { start: hour(0), end: hour(10) }]
+        );
+
+        // Finer prices than expected, e.g. 15 minute prices in an hourly zone, aren't gaps
+        let quarters: Vec<DateTime<Utc>> = (0..8).map(|i| hour(0) + Duration::minutes(15 * i)).collect();
+        assert!(find_gaps(&quarters, hour(0), hour(2), Duration::hours(1)).is_empty());
+        assert_eq!(
+            find_gaps(&quarters, hour(0), hour(3), Duration::minutes(15)),
+            vec![Gap { start: hour(2), end: hour(3) }]
+        );
+    }
+
+    #[test]
+    fn test_missing_merges_the_stores() {
+        let mut gaps = BTreeMap::new();
+        gaps.insert("InfluxDB", vec![Gap { start: hour(2), end: hour(4) }, Gap { start: hour(8), end: hour(9) }]);
+        gaps.insert("TimescaleDB", vec![Gap { start: hour(3), end: hour(6) }]);
+        gaps.insert("SQLite", vec![]);
+
+        let report = GapReport {
+            in_domain: "10YFI-1--------U".to_string(),
+            out_domain: "10YFI-1--------U".to_string(),
+            start: hour(0),
+            end: hour(10),
+            resolution_minutes: 60,
+            gaps,
+            errors: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            report.missing(),
+            vec![
+                (Gap { start: hour(2), end: hour(6) }, vec!["InfluxDB", "TimescaleDB"]),
+                (Gap { start: hour(8), end: hour(9) }, vec!["InfluxDB"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncate_leaves_the_rest_to_the_cursor() {
+        let mut gaps = BTreeMap::new();
+        gaps.insert("InfluxDB", vec![Gap { start: hour(2), end: hour(4) }, Gap { start: hour(8), end: hour(9) }]);
+        gaps.insert("SQLite", vec![Gap { start: hour(0), end: hour(10) }]);
+
+        let mut report = GapReport {
+            in_domain: "10YFI-1--------U".to_string(),
+            out_domain: "10YFI-1--------U".to_string(),
+            start: hour(0),
+            end: hour(10),
+            resolution_minutes: 60,
+            gaps,
+            errors: BTreeMap::new(),
+        };
+        report.truncate("InfluxDB", hour(3));
+        // An empty store is fetched from the start time by the regular fetch
+        report.truncate("SQLite", hour(0));
+
+        assert_eq!(report.gaps["InfluxDB"], vec![Gap { start: hour(2), end: hour(3) }]);
+        assert!(report.gaps["SQLite"].is_empty());
+    }
+}
@@ -1,169 +1,230 @@
-use std::str::FromStr;
+use std::cmp;
 
-use api::PublicationMarketDocument;
-use chrono::Duration as ChronoDuration;
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use influxdb::{Client, InfluxDbWriteable, ReadQuery};
-use iso8601_duration::Duration as IsoDuration;
 
-use super::price_data::PriceData;
+use super::price_data::{PriceComponentsData, PriceData};
+use crate::settings::app_config::InfluxDbConfig;
+use crate::settings::config_model::SettingsConfig;
+use crate::storage::price_store::{PricePoint, PriceStore, StoredPrice};
 
-pub fn is_enabled() -> bool {
-    dotenv::var("INFLUXDB_ENABLED")
-        .map(|var| var.parse::<bool>())
-        .unwrap_or(Ok(false))
-        .unwrap()
+/// Points per write request.
+pub const BATCH_SIZE: usize = 5000;
+
+pub struct InfluxStore {
+    config: InfluxDbConfig,
 }
 
-pub async fn upsert_document_into_influxdb(
-    document: &PublicationMarketDocument,
-    in_domain: &str,
-    out_domain: &str,
-) -> Result<(), anyhow::Error> {
-    if !is_enabled() {
-        return Ok(());
+impl InfluxStore {
+    pub fn new(config: InfluxDbConfig) -> InfluxStore {
+        InfluxStore { config }
     }
 
-    let mut messages = Vec::new();
+    async fn read_components(
+        &self,
+        client: &Client,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<PriceComponentsData>, anyhow::Error> {
+        let read_query = ReadQuery::new(format!("SELECT price, type_tag, in_domain_tag, out_domain_tag, tax_percentage, margin, electricity_tax, transfer_fee, consumer_price FROM dayAheadPrices WHERE type_tag='A44' AND in_domain_tag={} AND out_domain_tag={} AND time >= '{}' AND time < '{}' ORDER BY time", influxql_string(in_domain), influxql_string(out_domain), start.to_rfc3339(), end.to_rfc3339()));
 
-    let client = connect_to_db().await;
-    for time_serie in document.time_series.iter() {
-        for period in time_serie.period.iter() {
-            let start = &period.time_interval.start_as_utc();
-            let end = &period.time_interval.end_as_utc();
-            if start.is_none() || end.is_none() {
-                messages.push("InfluxDB | Skipping logging because start or end time couldn't be parsed".to_string());
-                continue;
-            }
-        
-            let parsed_duration = IsoDuration::from_str(&period.resolution).expect("Failed to parse duration");
-            let resolution = ChronoDuration::seconds(parsed_duration.to_std().unwrap().as_secs() as i64);
-            let mut last_price = None;
-            let mut current_time = start.unwrap();
-        
-            while current_time < end.unwrap() {
-                let position = ((current_time - start.unwrap()).num_seconds() / resolution.num_seconds()) + 1;
-                let point = period.point.iter().find(|p| p.position == position as i32);
-                let price = if let Some(point) = point {
-                    last_price = Some(point.price);
-                    point.price
-                } else {
-                    last_price.unwrap_or(0.0)
-                };
-        
-                // Delete the current row if it's dirty
-                delete_if_dirty(&client, in_domain, out_domain, &current_time).await;
-        
-                let current_data = PriceData {
-                    time: current_time,
-                    type_tag: document.r#type.to_string(),
-                    in_domain_tag: in_domain.to_string(),
-                    out_domain_tag: out_domain.to_string(),
-                    document_type: document.r#type.to_string(),
-                    in_domain: in_domain.to_string(),
-                    out_domain: out_domain.to_string(),
-                    currency: time_serie.currency_unit.to_string(),
-                    price_measure: time_serie.price_measure_unit.to_string(),
-                    curve_type: time_serie.curve_type.to_string(),
-                    timestamp: current_time.format("%Y-%m-%dT%H:%MZ").to_string(),
-                    price: price,
-                    dirty: None,
-                };
-        
-                let write_result = client
-                    .query(&current_data.into_query("dayAheadPrices"))
-                    .await;
-                if let Err(err) = write_result {
-                    error!("Error writing to db: {}", err)
-                }
-        
-                messages.push(format!("InfluxDB | {} - {:.2}", current_time, price));
-        
-                current_time = current_time + resolution;
+        let mut result = client.json_query(read_query).await?;
+        let result = result.deserialize_next::<PriceComponentsData>()?;
+
+        Ok(result.series.into_iter().flat_map(|series| series.values).collect())
+    }
+}
+
+#[async_trait]
+impl PriceStore for InfluxStore {
+    fn name(&self) -> &'static str {
+        "InfluxDB"
+    }
+
+    async fn upsert_points(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        points: &[PricePoint],
+        settings: &SettingsConfig,
+    ) -> Result<(), anyhow::Error> {
+        let mut points = points.to_vec();
+        points.sort_by_key(|point| point.time);
+
+        let client = connect_to_db(&self.config).await;
+        for run in contiguous_runs(&points) {
+            delete_dirty_range(&client, in_domain, out_domain, run[0].time, run[run.len() - 1].time).await?;
+        }
+
+        for batch in points.chunks(BATCH_SIZE) {
+            let mut queries = Vec::new();
+            for point in batch.iter() {
+                let consumer_price = settings.consumer_price(point.time, point.price)?;
+
+                let current_data = PriceData::from_point(point, in_domain, out_domain, &consumer_price);
+                queries.push(current_data.into_query("dayAheadPrices"));
             }
+
+            client.query(queries).await?;
         }
+
+        info!("InfluxDB | {} prices written for {}/{}", points.len(), in_domain, out_domain);
+
+        Ok(())
     }
 
-    let all_messages = messages.join("\n");
-    info!("{}", all_messages);
+    async fn latest_time(&self, in_domain: &str, out_domain: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        let client = connect_to_db(&self.config).await;
 
-    Ok(())
-}
+        let read_query = ReadQuery::new(format!("SELECT * FROM (SELECT * FROM dayAheadPrices fill(-111)) WHERE type_tag='A44' AND in_domain_tag={} AND out_domain_tag={} AND dirty = -111 ORDER BY time DESC LIMIT 1", influxql_string(in_domain), influxql_string(out_domain)));
 
-pub async fn get_latest_time(in_domain: &str, out_domain: &str) -> Option<chrono::DateTime<Utc>> {
-    let client = connect_to_db().await;
+        let result = client
+            .json_query(read_query)
+            .await
+            .and_then(|mut db_result| db_result.deserialize_next::<PriceData>())?;
 
-    let read_query = ReadQuery::new(format!("SELECT * FROM (SELECT * FROM dayAheadPrices fill(-111)) WHERE type_tag='A44' AND in_domain_tag='{}' AND out_domain_tag='{}' AND dirty = -111 ORDER BY time DESC LIMIT 1", in_domain, out_domain));
+        Ok(result
+            .series
+            .first()
+            .and_then(|series| series.values.first())
+            .map(|data| data.time))
+    }
+
+    async fn read_range(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<StoredPrice>, anyhow::Error> {
+        let client = connect_to_db(&self.config).await;
+        let stored = self.read_components(&client, in_domain, out_domain, start, end).await?;
+
+        Ok(stored
+            .into_iter()
+            .map(|data| StoredPrice {
+                time: data.time,
+                price: data.price,
+                tax_percentage: data.tax_percentage,
+                margin: data.margin,
+                electricity_tax: data.electricity_tax,
+                transfer_fee: data.transfer_fee,
+            })
+            .collect())
+    }
 
-    let read_result = client
-        .json_query(read_query)
-        .await
-        .and_then(|mut db_result| db_result.deserialize_next::<PriceData>());
+    /// Only the tax and price component fields are rewritten, InfluxDB merges them into the existing points.
+    async fn retax(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        settings: &SettingsConfig,
+    ) -> Result<u64, anyhow::Error> {
+        let client = connect_to_db(&self.config).await;
 
-    // let read_result = client.query(read_query).await;
-    match read_result {
-        Ok(result) => {
-            if result.series.len() > 0 && result.series[0].values.len() > 0 {
-                let data = &result.series[0].values[0];
-                debug!("{:?}", data);
-                return Some(data.time);
+        let mut queries = Vec::new();
+        for stored in self.read_components(&client, in_domain, out_domain, start, end).await? {
+            let consumer_price = settings.consumer_price(stored.time, stored.price)?;
+            let current_data = PriceComponentsData {
+                tax_percentage: Some(consumer_price.tax_percentage),
+                margin: Some(consumer_price.margin),
+                electricity_tax: Some(consumer_price.electricity_tax),
+                transfer_fee: Some(consumer_price.transfer_fee),
+                consumer_price: Some(consumer_price.total),
+                ..stored.clone()
+            };
+            if current_data != stored {
+                queries.push(current_data.into_query("dayAheadPrices"));
             }
         }
-        Err(err) => {
-            error!("Error reading dayAheadPrices from the db: {}", err);
+
+        let changed = queries.len() as u64;
+        while !queries.is_empty() {
+            let rest = queries.split_off(cmp::min(BATCH_SIZE, queries.len()));
+            client.query(queries).await?;
+            queries = rest;
         }
+
+        Ok(changed)
     }
 
-    None
-}
+    async fn health(&self) -> Result<(), anyhow::Error> {
+        connect_to_db(&self.config).await.ping().await?;
 
-async fn connect_to_db() -> Client {
-    let database_url = dotenv::var("DATABASE_URL").unwrap_or("http://localhost:8086".to_string());
-    let database_name = dotenv::var("DATABASE_NAME").unwrap_or("entsoe".to_string());
-    let username = dotenv::var("INFLUXDB_USERNAME").unwrap_or("".to_string());
-    let password = dotenv::var("INFLUXDB_PASSWORD").unwrap_or("".to_string());
-
-    let client = Client::new(&database_url, &database_name);
-    if !username.is_empty() && !password.is_empty() {
-        client.with_auth(&username, &password)
-    } else {
-        client
+        Ok(())
     }
 }
 
-async fn delete_if_dirty(client: &Client, in_domain: &str, out_domain: &str, time: &DateTime<Utc>) {
-    let read_query = ReadQuery::new(format!("SELECT * FROM dayAheadPrices WHERE type_tag='A44' AND in_domain_tag='{}' AND out_domain_tag='{}' AND time = '{}' AND dirty = 1 ORDER BY time DESC LIMIT 1", in_domain, out_domain, time.to_rfc3339()));
-
-    let read_result = client
-        .json_query(read_query)
-        .await
-        .and_then(|mut db_result| db_result.deserialize_next::<PriceData>());
-
-    match read_result {
-        Ok(result) => {
-            if result.series.len() > 0 && result.series[0].values.len() > 0 {
-                info!("Query: {}", format!("DELETE FROM dayAheadPrices WHERE type_tag='A44' AND in_domain_tag='{}' AND out_domain_tag='{}' AND time = '{}' AND dirty = 1", in_domain, out_domain, time.to_rfc3339()));
-                // let read_query = ReadQuery::new(format!("DELETE FROM dayAheadPrices WHERE time = '{}'", time.to_rfc3339()));
-                let read_query = ReadQuery::new(format!("DELETE FROM dayAheadPrices WHERE type_tag='A44' AND in_domain_tag='{}' AND out_domain_tag='{}' AND time = '{}'", in_domain, out_domain, time.to_rfc3339()));
-
-                let read_result = client.query(read_query).await;
-                match read_result {
-                    Ok(_) => {}
-                    Err(err) => {
-                        error!("Error deleting dayAheadPrice from the db: {}", err);
-                    }
-                }
-            }
+async fn connect_to_db(config: &InfluxDbConfig) -> Client {
+    let client = Client::new(&config.url, &config.database);
+    match (&config.username, &config.password) {
+        (Some(username), Some(password)) if !username.is_empty() && !password.is_empty() => {
+            client.with_auth(username, password)
         }
-        Err(err) => {
-            error!("Error reading dayAheadPrices from the db: {}", err);
+        _ => client,
+    }
+}
+
+/// Deletes the zone's prices in `[start, end]` if any of them is marked dirty. The dirty field can't be
+/// unset by overwriting, so the whole range is deleted before it's written again.
+async fn delete_dirty_range(
+    client: &Client,
+    in_domain: &str,
+    out_domain: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    let range = format!("type_tag='A44' AND in_domain_tag={} AND out_domain_tag={} AND time >= '{}' AND time <= '{}'", influxql_string(in_domain), influxql_string(out_domain), start.to_rfc3339(), end.to_rfc3339());
+
+    let read_query = ReadQuery::new(format!("SELECT * FROM dayAheadPrices WHERE {} AND dirty = 1 LIMIT 1", range));
+    let result = client.json_query(read_query).await?.deserialize_next::<PriceData>()?;
+    if result.series.iter().all(|series| series.values.is_empty()) {
+        return Ok(());
+    }
+
+    info!("Deleting the dirty range {} - {} of {}/{}", start, end, in_domain, out_domain);
+    client
+        .query(ReadQuery::new(format!("DELETE FROM dayAheadPrices WHERE {}", range)))
+        .await?;
+
+    Ok(())
+}
+
+/// Quotes the value as an InfluxQL string literal, so that it can't end the literal early.
+fn influxql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Splits the time ordered points into runs without gaps, so that deleting a run's range only
+/// deletes prices that are written again. A point further than its resolution from the previous one starts a new run.
+fn contiguous_runs(points: &[PricePoint]) -> Vec<&[PricePoint]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+
+    for i in 1..points.len() {
+        if points[i].time - points[i - 1].time > points[i - 1].resolution {
+            runs.push(&points[start..i]);
+            start = i;
         }
     }
+    if start < points.len() {
+        runs.push(&points[start..]);
+    }
+
+    runs
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::{Duration, TimeZone};
+
     use crate::dotenv;
+    use crate::settings::config::load_app_config;
 
     use super::*;
 
@@ -171,10 +232,50 @@ mod tests {
     async fn test_get_latest_time() {
         dotenv().ok();
 
-        let in_domain = dotenv::var("IN_DOMAIN").unwrap();
-        let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
+        let config = load_app_config(format!("configs/{}.yaml", "production")).unwrap();
+        let zone = config.default_zone().unwrap();
 
-        let response = get_latest_time(&in_domain, &out_domain).await;
+        let store = InfluxStore::new(config.influxdb.clone());
+        let response = store.latest_time(&zone.in_domain, &zone.out_domain).await;
         info!("Last time in InfluxDB is {:?}", response);
     }
+
+    #[test]
+    fn test_contiguous_runs() {
+        let point = |minutes: i64, resolution: i64| PricePoint {
+            time: Utc.ymd(2024, 1, 1).and_hms(0, 0, 0) + Duration::minutes(minutes),
+            price: 0.0,
+            document_type: "A44".to_string(),
+            currency: "EUR".to_string(),
+            measure_unit: "MWH".to_string(),
+            curve_type: "A03".to_string(),
+            resolution: Duration::minutes(resolution),
+        };
+        let points: Vec<PricePoint> = [(0, 15), (15, 15), (30, 15), (45, 15), (120, 60), (180, 60), (240, 60), (300, 60)]
+            .iter()
+            .map(|(minutes, resolution)| point(*minutes, *resolution))
+            .collect();
+
+        let runs: Vec<usize> = contiguous_runs(&points).iter().map(|run| run.len()).collect();
+        assert_eq!(runs, vec![4, 4]);
+        assert_eq!(contiguous_runs(&points[..1]).len(), 1);
+
+        // The missing 01:00 isn't deleted along with 00:00 and 02:00
+        let points: Vec<PricePoint> = [0, 120, 180].iter().map(|minutes| point(*minutes, 60)).collect();
+        let runs: Vec<usize> = contiguous_runs(&points).iter().map(|run| run.len()).collect();
+        assert_eq!(runs, vec![1, 2]);
+        assert!(contiguous_runs(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_influxql_string_escapes_quotes() {
+        assert_eq!(influxql_string("10YFI-1--------U"), "'10YFI-1--------U'");
+        assert_eq!(
+            influxql_string("x' OR in_domain_tag != 'x"),
+            "'x\\' OR in_domain_tag != \\'x'"
+        );
+        // A trailing backslash can't escape the closing quote
+        assert_eq!(influxql_string("x\\"), "'x\\\\'");
+        assert_eq!(influxql_string("x\\'; DELETE FROM dayAheadPrices"), "'x\\\\\\'; DELETE FROM dayAheadPrices'");
+    }
 }
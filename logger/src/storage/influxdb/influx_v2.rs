@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use influxdb::{InfluxDbWriteable, Query, WriteQuery};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use super::influx::BATCH_SIZE;
+use super::price_data::{PriceComponentsData, PriceData};
+use crate::settings::app_config::InfluxDbConfig;
+use crate::settings::config_model::SettingsConfig;
+use crate::storage::price_store::{PricePoint, PriceStore, StoredPrice};
+
+const MEASUREMENT: &str = "dayAheadPrices";
+
+/// Flux ranges end at `now()` by default, which would leave out the day-ahead prices.
+const RANGE_STOP: &str = "2100-01-01T00:00:00Z";
+
+const STORED_FIELDS: [&str; 5] = ["price", "tax_percentage", "margin", "electricity_tax", "transfer_fee"];
+
+#[derive(Debug, Error)]
+#[error("InfluxDB responded with {status}: {body}")]
+struct ResponseError {
+    status: StatusCode,
+    body: String,
+}
+
+/// InfluxDB 2.x and 3.x store. Both take line protocol from `/api/v2/write`, 2.x is queried with Flux
+/// and 3.x with SQL.
+pub struct InfluxV2Store {
+    config: InfluxDbConfig,
+    client: Client,
+}
+
+impl InfluxV2Store {
+    pub fn new(config: InfluxDbConfig) -> InfluxV2Store {
+        InfluxV2Store {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.config.token {
+            Some(token) if !token.is_empty() => {
+                let scheme = if self.config.version == 2 { "Token" } else { "Bearer" };
+                request.header("Authorization", format!("{} {}", scheme, token))
+            }
+            _ => request,
+        }
+    }
+
+    async fn write(&self, queries: Vec<WriteQuery>) -> Result<(), anyhow::Error> {
+        if queries.is_empty() {
+            return Ok(());
+        }
+
+        let body = queries.build()?.get();
+        let request = self
+            .client
+            .post(format!("{}/api/v2/write", self.config.url.trim_end_matches('/')))
+            .query(&[
+                ("org", self.config.org.as_deref().unwrap_or_default()),
+                ("bucket", self.config.bucket()),
+                ("precision", "ns"),
+            ])
+            .body(body);
+        send(self.request(request)).await?;
+
+        Ok(())
+    }
+
+    /// Rows of the zone's prices with the `fields`, each as column name to value.
+    async fn query_rows(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+        fields: &[&str],
+    ) -> Result<Vec<HashMap<String, String>>, anyhow::Error> {
+        let url = self.config.url.trim_end_matches('/');
+        if self.config.version == 2 {
+            let query = flux_query(self.config.bucket(), in_domain, out_domain, range, fields);
+            let request = self
+                .client
+                .post(format!("{}/api/v2/query", url))
+                .query(&[("org", self.config.org.as_deref().unwrap_or_default())])
+                .header("Accept", "application/csv")
+                .json(&json!({
+                    "query": query,
+                    "type": "flux",
+                    "dialect": { "header": true, "annotations": [] },
+                }));
+
+            Ok(parse_csv(&send(self.request(request)).await?)?)
+        } else {
+            // InfluxDB 3 creates the database and the table on the first write
+            if !self.has_table().await? {
+                return Ok(Vec::new());
+            }
+
+            let rows = self.query_sql(&sql_query(in_domain, out_domain, range, fields)).await?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|(column, value)| match value {
+                            Value::String(value) => (column, value),
+                            Value::Null => (column, String::new()),
+                            value => (column, value.to_string()),
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    async fn query_sql(&self, query: &str) -> Result<Vec<HashMap<String, Value>>, anyhow::Error> {
+        let request = self
+            .client
+            .post(format!("{}/api/v3/query_sql", self.config.url.trim_end_matches('/')))
+            .json(&json!({ "db": self.config.bucket(), "q": query, "format": "json" }));
+
+        Ok(serde_json::from_str(&send(self.request(request)).await?)?)
+    }
+
+    async fn has_table(&self) -> Result<bool, anyhow::Error> {
+        let query = format!(
+            "SELECT table_name FROM information_schema.tables WHERE table_name = {}",
+            sql_string(MEASUREMENT)
+        );
+        match self.query_sql(&query).await {
+            Ok(rows) => Ok(!rows.is_empty()),
+            Err(err) if is_not_found(&err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceStore for InfluxV2Store {
+    fn name(&self) -> &'static str {
+        "InfluxDB"
+    }
+
+    async fn upsert_points(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        points: &[PricePoint],
+        settings: &SettingsConfig,
+    ) -> Result<(), anyhow::Error> {
+        for batch in points.chunks(BATCH_SIZE) {
+            let mut queries = Vec::new();
+            for point in batch.iter() {
+                let consumer_price = settings.consumer_price(point.time, point.price)?;
+
+                let current_data = PriceData::from_point(point, in_domain, out_domain, &consumer_price);
+                queries.push(current_data.into_query(MEASUREMENT));
+            }
+
+            self.write(queries).await?;
+        }
+        info!("InfluxDB | {} prices written for {}/{}", points.len(), in_domain, out_domain);
+
+        Ok(())
+    }
+
+    async fn latest_time(&self, in_domain: &str, out_domain: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        let rows = self.query_rows(in_domain, out_domain, None, &["price"]).await?;
+
+        Ok(rows.last().and_then(|row| row.get("time")).and_then(|time| parse_time(time)))
+    }
+
+    async fn read_range(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<StoredPrice>, anyhow::Error> {
+        let rows = self
+            .query_rows(in_domain, out_domain, Some((start, end)), &STORED_FIELDS)
+            .await?;
+
+        let mut prices = Vec::new();
+        for row in rows.iter() {
+            let field = |name: &str| row.get(name).and_then(|value| value.parse::<f32>().ok());
+            let time = row.get("time").map(String::as_str).unwrap_or_default();
+            prices.push(StoredPrice {
+                time: parse_time(time).ok_or_else(|| anyhow::anyhow!("Invalid time {:?} from InfluxDB", time))?,
+                price: field("price").ok_or_else(|| anyhow::anyhow!("Missing price at {} from InfluxDB", time))?,
+                tax_percentage: field("tax_percentage"),
+                margin: field("margin"),
+                electricity_tax: field("electricity_tax"),
+                transfer_fee: field("transfer_fee"),
+            });
+        }
+
+        Ok(prices)
+    }
+
+    /// Only the tax and price component fields are rewritten, InfluxDB merges them into the existing points.
+    async fn retax(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        settings: &SettingsConfig,
+    ) -> Result<u64, anyhow::Error> {
+        let mut queries = Vec::new();
+        for stored in self.read_range(in_domain, out_domain, start, end).await? {
+            let consumer_price = settings.consumer_price(stored.time, stored.price)?;
+            let components = [consumer_price.tax_percentage, consumer_price.margin, consumer_price.electricity_tax, consumer_price.transfer_fee];
+            let stored_components = [stored.tax_percentage, stored.margin, stored.electricity_tax, stored.transfer_fee];
+            if stored_components == components.map(Some) {
+                continue;
+            }
+
+            let current_data = PriceComponentsData {
+                time: stored.time,
+                type_tag: "A44".to_string(),
+                in_domain_tag: in_domain.to_string(),
+                out_domain_tag: out_domain.to_string(),
+                price: stored.price,
+                tax_percentage: Some(consumer_price.tax_percentage),
+                margin: Some(consumer_price.margin),
+                electricity_tax: Some(consumer_price.electricity_tax),
+                transfer_fee: Some(consumer_price.transfer_fee),
+                consumer_price: Some(consumer_price.total),
+            };
+            queries.push(current_data.into_query(MEASUREMENT));
+        }
+
+        let changed = queries.len() as u64;
+        self.write(queries).await?;
+
+        Ok(changed)
+    }
+
+    async fn health(&self) -> Result<(), anyhow::Error> {
+        let request = self
+            .client
+            .get(format!("{}/health", self.config.url.trim_end_matches('/')));
+        send(self.request(request)).await?;
+
+        Ok(())
+    }
+}
+
+async fn send(request: RequestBuilder) -> Result<String, anyhow::Error> {
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(ResponseError { status, body }.into());
+    }
+
+    Ok(body)
+}
+
+/// InfluxDB 3 answers 404 for a database that hasn't been written to yet.
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ResponseError>()
+        .is_some_and(|err| err.status == StatusCode::NOT_FOUND)
+}
+
+/// Flux query for the fields pivoted into one row per time, or only the latest row without a range.
+fn flux_query(
+    bucket: &str,
+    in_domain: &str,
+    out_domain: &str,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    fields: &[&str],
+) -> String {
+    let (start, stop) = match range {
+        Some((start, end)) => (
+            start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            end.to_rfc3339_opts(SecondsFormat::Secs, true),
+        ),
+        None => ("0".to_string(), RANGE_STOP.to_string()),
+    };
+    let fields = fields
+        .iter()
+        .map(|field| format!("r._field == {}", flux_string(field)))
+        .collect::<Vec<String>>()
+        .join(" or ");
+
+    let mut query = format!(
+        "from(bucket: {})
+  |> range(start: {}, stop: {})
+  |> filter(fn: (r) => r._measurement == {} and r.type_tag == \"A44\" and r.in_domain_tag == {} and r.out_domain_tag == {})
+  |> filter(fn: (r) => {})
+  |> pivot(rowKey: [\"_time\"], columnKey: [\"_field\"], valueColumn: \"_value\")
+  |> rename(columns: {{_time: \"time\"}})
+  |> sort(columns: [\"time\"])",
+        flux_string(bucket),
+        start,
+        stop,
+        flux_string(MEASUREMENT),
+        flux_string(in_domain),
+        flux_string(out_domain),
+        fields
+    );
+    if range.is_none() {
+        query.push_str("\n  |> tail(n: 1)");
+    }
+
+    query
+}
+
+/// SQL query for the fields, or only the latest row without a range.
+fn sql_query(
+    in_domain: &str,
+    out_domain: &str,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    fields: &[&str],
+) -> String {
+    let mut query = format!(
+        "SELECT time, {} FROM \"{}\" WHERE type_tag = 'A44' AND in_domain_tag = {} AND out_domain_tag = {}",
+        fields.join(", "),
+        MEASUREMENT,
+        sql_string(in_domain),
+        sql_string(out_domain)
+    );
+    match range {
+        Some((start, end)) => query.push_str(&format!(
+            " AND time >= {} AND time < {} ORDER BY time",
+            sql_string(&start.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            sql_string(&end.to_rfc3339_opts(SecondsFormat::Secs, true))
+        )),
+        None => query.push_str(" ORDER BY time DESC LIMIT 1"),
+    }
+
+    query
+}
+
+fn flux_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Flux CSV results without annotations, where each table starts with its own header row.
+fn parse_csv(body: &str) -> Result<Vec<HashMap<String, String>>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let mut rows = Vec::new();
+    let mut header: Option<csv::StringRecord> = None;
+
+    // The blank lines between the tables are skipped by the reader, the header rows name the columns instead
+    for record in reader.records() {
+        let record = record?;
+        match &header {
+            Some(header) if !is_header(&record) => {
+                rows.push(header.iter().map(str::to_string).zip(record.iter().map(str::to_string)).collect())
+            }
+            _ => header = Some(record),
+        }
+    }
+
+    Ok(rows)
+}
+
+fn is_header(record: &csv::StringRecord) -> bool {
+    record.get(1) == Some("result") && record.get(2) == Some("table")
+}
+
+/// Flux returns RFC 3339 times, InfluxDB 3 SQL UTC times without an offset.
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+                .ok()
+                .map(|time| DateTime::from_utc(time, Utc))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_tables() {
+        let body = ",result,table,time,price,tax_percentage\r\n,_result,0,2024-01-01T00:00:00Z,10.5,24\r\n,_result,0,2024-01-01T01:00:00Z,11,\r\n\r\n,result,table,time,price\r\n,_result,1,2024-01-01T02:00:00Z,12\r\n";
+
+        let rows = parse_csv(body).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["price"], "10.5");
+        assert_eq!(rows[1]["tax_percentage"], "");
+        assert_eq!(rows[2]["time"], "2024-01-01T02:00:00Z");
+        assert!(!rows[2].contains_key("tax_percentage"));
+
+        // Quoted values may contain commas and quotes
+        let body = ",result,table,time,price,source\r\n,_result,0,2024-01-01T00:00:00Z,10.5,\"entsoe, \"\"A44\"\"\"\r\n";
+        let rows = parse_csv(body).unwrap();
+        assert_eq!(rows[0]["source"], "entsoe, \"A44\"");
+        assert_eq!(rows[0]["price"], "10.5");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let time = Utc.ymd(2024, 1, 1).and_hms(22, 0, 0);
+        assert_eq!(parse_time("2024-01-01T22:00:00Z"), Some(time));
+        assert_eq!(parse_time("2024-01-02T00:00:00+02:00"), Some(time));
+        assert_eq!(parse_time("2024-01-01T22:00:00"), Some(time));
+        assert_eq!(parse_time("2024-01-01T22:00:00.000000000"), Some(time));
+        assert_eq!(parse_time("yesterday"), None);
+    }
+
+    #[test]
+    fn test_not_found_is_the_response_status() {
+        let response = |status| anyhow::Error::from(ResponseError { status, body: "table not found".to_string() });
+
+        assert!(is_not_found(&response(StatusCode::NOT_FOUND)));
+        assert!(!is_not_found(&response(StatusCode::BAD_REQUEST)));
+        assert!(!is_not_found(&anyhow::anyhow!("database not found")));
+    }
+
+    #[test]
+    fn test_queries_quote_the_domains() {
+        let query = flux_query("prices", "10Y\"FI", "10YFI-1--------U", None, &["price"]);
+        assert!(query.contains("r.in_domain_tag == \"10Y\\\"FI\""), "{}", query);
+        assert!(query.contains("stop: 2100-01-01T00:00:00Z"), "{}", query);
+        assert!(query.ends_with("|> tail(n: 1)"), "{}", query);
+
+        let start = Utc.ymd(2024, 1, 1).and_hms(0, 0, 0);
+        let query = sql_query("10Y'FI", "10YFI-1--------U", Some((start, start)), &["price", "margin"]);
+        assert_eq!(
+            query,
+            "SELECT time, price, margin FROM \"dayAheadPrices\" WHERE type_tag = 'A44' AND in_domain_tag = '10Y''FI' AND out_domain_tag = '10YFI-1--------U' AND time >= '2024-01-01T00:00:00Z' AND time < '2024-01-01T00:00:00Z' ORDER BY time"
+        );
+    }
+}
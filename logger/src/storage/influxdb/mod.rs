@@ -1,2 +1,3 @@
 pub mod influx;
+pub mod influx_v2;
 pub mod price_data;
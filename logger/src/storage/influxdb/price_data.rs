@@ -2,6 +2,9 @@ use chrono::{DateTime, Utc};
 use influxdb::InfluxDbWriteable;
 use serde::{Deserialize, Serialize};
 
+use crate::settings::config_model::ConsumerPrice;
+use crate::storage::price_store::PricePoint;
+
 #[derive(Debug, InfluxDbWriteable, Serialize, Deserialize)]
 #[allow(non_snake_case)]
 pub struct PriceData {
@@ -20,5 +23,62 @@ pub struct PriceData {
     pub curve_type: String,
     pub timestamp: String,
     pub price: f32,
+    #[serde(default)]
+    pub tax_percentage: f32,
+    /// Price components in c/kWh without VAT
+    #[serde(default)]
+    pub margin: f32,
+    #[serde(default)]
+    pub electricity_tax: f32,
+    #[serde(default)]
+    pub transfer_fee: f32,
+    /// Total price in c/kWh with VAT
+    #[serde(default)]
+    pub consumer_price: f32,
     pub dirty: Option<i32>,
 }
+
+impl PriceData {
+    pub fn from_point(point: &PricePoint, in_domain: &str, out_domain: &str, consumer_price: &ConsumerPrice) -> PriceData {
+        PriceData {
+            time: point.time,
+            type_tag: point.document_type.clone(),
+            in_domain_tag: in_domain.to_string(),
+            out_domain_tag: out_domain.to_string(),
+            document_type: point.document_type.clone(),
+            in_domain: in_domain.to_string(),
+            out_domain: out_domain.to_string(),
+            currency: point.currency.clone(),
+            price_measure: point.measure_unit.clone(),
+            curve_type: point.curve_type.clone(),
+            timestamp: point.time.format("%Y-%m-%dT%H:%MZ").to_string(),
+            price: point.price,
+            tax_percentage: consumer_price.tax_percentage,
+            margin: consumer_price.margin,
+            electricity_tax: consumer_price.electricity_tax,
+            transfer_fee: consumer_price.transfer_fee,
+            consumer_price: consumer_price.total,
+            dirty: None,
+        }
+    }
+}
+
+/// Tax and price components of a stored price. Rows written before the components were
+/// added don't have them, so they are optional when read.
+#[derive(Debug, Clone, PartialEq, InfluxDbWriteable, Serialize, Deserialize)]
+pub struct PriceComponentsData {
+    pub time: DateTime<Utc>,
+    #[influxdb(tag)]
+    pub type_tag: String,
+    #[influxdb(tag)]
+    pub in_domain_tag: String,
+    #[influxdb(tag)]
+    pub out_domain_tag: String,
+    #[influxdb(ignore)]
+    pub price: f32,
+    pub tax_percentage: Option<f32>,
+    pub margin: Option<f32>,
+    pub electricity_tax: Option<f32>,
+    pub transfer_fee: Option<f32>,
+    pub consumer_price: Option<f32>,
+}
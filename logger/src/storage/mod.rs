@@ -1,2 +1,6 @@
+pub mod gaps;
 pub mod influxdb;
-pub mod timescaledb;
\ No newline at end of file
+pub mod price_store;
+pub mod retax;
+pub mod sqlitedb;
+pub mod timescaledb;
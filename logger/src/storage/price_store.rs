@@ -0,0 +1,417 @@
+use std::cmp;
+
+use api::{expand_period, parse_resolution, PublicationMarketDocument};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::settings::app_config::AppConfig;
+use crate::settings::config_model::SettingsConfig;
+use crate::storage::influxdb::influx::InfluxStore;
+use crate::storage::influxdb::influx_v2::InfluxV2Store;
+use crate::storage::sqlitedb::sqlite::SqliteStore;
+use crate::storage::timescaledb::aggregates::ZoneAggregates;
+use crate::storage::timescaledb::timescale::TimescaleStore;
+
+/// Price for one resolution step of a day-ahead document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricePoint {
+    pub time: DateTime<Utc>,
+    pub price: f32,
+    pub document_type: String,
+    pub currency: String,
+    pub measure_unit: String,
+    pub curve_type: String,
+    /// Step of the document period the price is from
+    pub resolution: Duration,
+}
+
+/// Price as stored, with the tax and price components written along with it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredPrice {
+    pub time: DateTime<Utc>,
+    pub price: f32,
+    pub tax_percentage: Option<f32>,
+    pub margin: Option<f32>,
+    pub electricity_tax: Option<f32>,
+    pub transfer_fee: Option<f32>,
+}
+
+/// Storage backend for the day-ahead prices.
+#[async_trait]
+pub trait PriceStore: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Inserts the points or replaces the stored ones, with the taxes from the settings.
+    async fn upsert_points(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        points: &[PricePoint],
+        settings: &SettingsConfig,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Time of the latest stored price for the zone, `None` if there's none.
+    async fn latest_time(&self, in_domain: &str, out_domain: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error>;
+
+    /// Stored prices in `[start, end)` ordered by time.
+    async fn read_range(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<StoredPrice>, anyhow::Error>;
+
+    /// Re-applies the settings to the stored prices in `[start, end)`, returns how many changed.
+    async fn retax(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        settings: &SettingsConfig,
+    ) -> Result<u64, anyhow::Error>;
+
+    async fn health(&self) -> Result<(), anyhow::Error>;
+
+    /// Called after the writes for a fetch, e.g. to refresh aggregates.
+    async fn after_write(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Creates or updates the schema, returns the applied migrations.
+    async fn migrate(&self) -> Result<Vec<String>, anyhow::Error> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoreHealth {
+    pub name: &'static str,
+    pub healthy: bool,
+    pub error: Option<String>,
+}
+
+/// The enabled price stores. Every write goes to all of them.
+pub struct PriceStores {
+    stores: Vec<Box<dyn PriceStore>>,
+}
+
+impl PriceStores {
+    pub fn new(stores: Vec<Box<dyn PriceStore>>) -> PriceStores {
+        PriceStores { stores }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Result<PriceStores, anyhow::Error> {
+        let mut stores: Vec<Box<dyn PriceStore>> = Vec::new();
+        if config.timescaledb.enabled {
+            let zones = config
+                .zones()
+                .iter()
+                .map(|zone| ZoneAggregates::new(&zone.in_domain, &zone.out_domain, config.zone_timezone(zone)))
+                .collect();
+            stores.push(Box::new(TimescaleStore::new(config.timescaledb.clone(), zones)?));
+        }
+        if config.influxdb.enabled {
+            if config.influxdb.version == 1 {
+                stores.push(Box::new(InfluxStore::new(config.influxdb.clone())));
+            } else {
+                stores.push(Box::new(InfluxV2Store::new(config.influxdb.clone())));
+            }
+        }
+        if config.sqlite.enabled {
+            stores.push(Box::new(SqliteStore::new(config.sqlite.clone())));
+        }
+
+        Ok(PriceStores::new(stores))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn PriceStore> {
+        self.stores.iter().map(|store| store.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stores.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.iter().map(|store| store.name()).collect()
+    }
+
+    /// Writes the points to the `targets` stores. A failing store doesn't stop the others, but fails the write.
+    pub async fn upsert_points(
+        &self,
+        targets: &[&str],
+        in_domain: &str,
+        out_domain: &str,
+        points: &[PricePoint],
+        settings: &SettingsConfig,
+    ) -> Result<(), anyhow::Error> {
+        let stores: Vec<&dyn PriceStore> = self.iter().filter(|store| targets.contains(&store.name())).collect();
+        let results = join_all(
+            stores
+                .iter()
+                .map(|store| store.upsert_points(in_domain, out_domain, points, settings)),
+        )
+        .await;
+
+        let mut failed = Vec::new();
+        for (store, result) in stores.iter().zip(results) {
+            if let Err(err) = result {
+                error!("Error inserting into {}: {:?}", store.name(), err);
+                failed.push(store.name());
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Writing the prices to {} failed", failed.join(", ")))
+        }
+    }
+
+    /// Where each store continues fetching the zone from: its latest price, but not before `start_time`.
+    /// A store that can't be reached is an error, so that it isn't fetched for from `start_time` again.
+    pub async fn cursors(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start_time: DateTime<Utc>,
+    ) -> Vec<(&'static str, Result<DateTime<Utc>, anyhow::Error>)> {
+        let latest_times = join_all(self.iter().map(|store| store.latest_time(in_domain, out_domain))).await;
+
+        self.iter()
+            .zip(latest_times)
+            .map(|(store, latest_time)| {
+                let cursor = latest_time.map(|latest_time| cmp::max(start_time, latest_time.unwrap_or(start_time)));
+                if let Ok(cursor) = &cursor {
+                    debug!("Latest {} time: {}", store.name(), cursor);
+                }
+                (store.name(), cursor)
+            })
+            .collect()
+    }
+
+    pub async fn after_write(&self) -> Result<(), anyhow::Error> {
+        for store in self.iter() {
+            store.after_write().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Migrates the stores one by one, returns the applied migrations prefixed with the store name.
+    pub async fn migrate(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut applied = Vec::new();
+        for store in self.iter() {
+            for migration in store.migrate().await? {
+                applied.push(format!("{}: {}", store.name(), migration));
+            }
+        }
+
+        Ok(applied)
+    }
+
+    pub async fn health(&self) -> Vec<StoreHealth> {
+        let results = join_all(self.iter().map(|store| store.health())).await;
+
+        self.iter()
+            .zip(results)
+            .map(|(store, result)| StoreHealth {
+                name: store.name(),
+                healthy: result.is_ok(),
+                error: result.err().map(|err| err.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Expands the document into one point per resolution step. Positions left out of the
+/// document repeat the previous price.
+pub fn document_points(document: &PublicationMarketDocument) -> Result<Vec<PricePoint>, anyhow::Error> {
+    let mut points = Vec::new();
+
+    for time_serie in document.time_series.iter() {
+        for period in time_serie.period.iter() {
+            let resolution = parse_resolution(&period.resolution)?;
+            let prices: Vec<(i32, f32)> = period.point.iter().map(|point| (point.position, point.price)).collect();
+            for (time, price) in expand_period(&period.time_interval, &period.resolution, &prices)? {
+                points.push(PricePoint {
+                    time,
+                    price,
+                    document_type: document.r#type.to_string(),
+                    currency: time_serie.currency_unit.to_string(),
+                    measure_unit: time_serie.price_measure_unit.to_string(),
+                    curve_type: time_serie.curve_type.to_string(),
+                    resolution,
+                });
+            }
+        }
+    }
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use api::{parse_document, EntsoeDocument};
+    use chrono::TimeZone;
+
+    use super::*;
+
+    struct FixedStore(&'static str, Option<DateTime<Utc>>);
+
+    #[async_trait]
+    impl PriceStore for FixedStore {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn upsert_points(&self, _: &str, _: &str, _: &[PricePoint], _: &SettingsConfig) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+
+        async fn latest_time(&self, _: &str, _: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+            Ok(self.1)
+        }
+
+        async fn read_range(&self, _: &str, _: &str, _: DateTime<Utc>, _: DateTime<Utc>) -> Result<Vec<StoredPrice>, anyhow::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn retax(&self, _: &str, _: &str, _: DateTime<Utc>, _: DateTime<Utc>, _: &SettingsConfig) -> Result<u64, anyhow::Error> {
+            Ok(0)
+        }
+
+        async fn health(&self) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+    }
+
+    struct UnreachableStore;
+
+    #[async_trait]
+    impl PriceStore for UnreachableStore {
+        fn name(&self) -> &'static str {
+            "Unreachable"
+        }
+
+        async fn upsert_points(&self, _: &str, _: &str, _: &[PricePoint], _: &SettingsConfig) -> Result<(), anyhow::Error> {
+            anyhow::bail!("Connection refused")
+        }
+
+        async fn latest_time(&self, _: &str, _: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+            anyhow::bail!("Connection refused")
+        }
+
+        async fn read_range(&self, _: &str, _: &str, _: DateTime<Utc>, _: DateTime<Utc>) -> Result<Vec<StoredPrice>, anyhow::Error> {
+            anyhow::bail!("Connection refused")
+        }
+
+        async fn retax(&self, _: &str, _: &str, _: DateTime<Utc>, _: DateTime<Utc>, _: &SettingsConfig) -> Result<u64, anyhow::Error> {
+            anyhow::bail!("Connection refused")
+        }
+
+        async fn health(&self) -> Result<(), anyhow::Error> {
+            anyhow::bail!("Connection refused")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_each_store_has_its_own_cursor() {
+        let start_time = Utc.ymd(2024, 1, 1).and_hms(0, 0, 0);
+        let stores = PriceStores::new(vec![
+            Box::new(FixedStore("Ahead", Some(Utc.ymd(2024, 3, 1).and_hms(0, 0, 0)))),
+            Box::new(FixedStore("Behind", Some(Utc.ymd(2024, 2, 1).and_hms(0, 0, 0)))),
+            // A store without data starts from the configured start time
+            Box::new(FixedStore("Empty", None)),
+            Box::new(FixedStore("Old", Some(Utc.ymd(2023, 1, 1).and_hms(0, 0, 0)))),
+            Box::new(UnreachableStore),
+        ]);
+
+        let cursors = stores.cursors("in", "out", start_time).await;
+        let ok: Vec<(&str, DateTime<Utc>)> = cursors
+            .iter()
+            .filter_map(|(store, cursor)| cursor.as_ref().ok().map(|cursor| (*store, *cursor)))
+            .collect();
+        assert_eq!(
+            ok,
+            vec![
+                ("Ahead", Utc.ymd(2024, 3, 1).and_hms(0, 0, 0)),
+                ("Behind", Utc.ymd(2024, 2, 1).and_hms(0, 0, 0)),
+                ("Empty", start_time),
+                ("Old", start_time),
+            ]
+        );
+
+        // Not reset to the start time
+        assert_eq!(cursors[4].0, "Unreachable");
+        assert!(cursors[4].1.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_points_writes_only_to_the_targets() {
+        let stores = PriceStores::new(vec![Box::new(FixedStore("Fixed", None)), Box::new(UnreachableStore)]);
+        let settings: SettingsConfig = serde_yaml::from_str("settings: []").unwrap();
+
+        assert!(stores.upsert_points(&["Fixed"], "in", "out", &[], &settings).await.is_ok());
+        let err = stores.upsert_points(&stores.names(), "in", "out", &[], &settings).await.unwrap_err();
+        assert_eq!(err.to_string(), "Writing the prices to Unreachable failed");
+    }
+
+    #[test]
+    fn test_document_points_repeat_missing_positions() {
+        let document = r#"
+        <Publication_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-3:publicationdocument:7:3">
+            <mRID>1</mRID>
+            <revisionNumber>1</revisionNumber>
+            <type>A44</type>
+            <sender_MarketParticipant.mRID codingScheme="A01">10X1001A1001A450</sender_MarketParticipant.mRID>
+            <sender_MarketParticipant.marketRole.type>A32</sender_MarketParticipant.marketRole.type>
+            <receiver_MarketParticipant.mRID codingScheme="A01">10X1001A1001A450</receiver_MarketParticipant.mRID>
+            <receiver_MarketParticipant.marketRole.type>A33</receiver_MarketParticipant.marketRole.type>
+            <createdDateTime>2024-01-01T12:00:00Z</createdDateTime>
+            <period.timeInterval>
+                <start>2024-01-01T23:00Z</start>
+                <end>2024-01-02T00:00Z</end>
+            </period.timeInterval>
+            <TimeSeries>
+                <mRID>1</mRID>
+                <businessType>A62</businessType>
+                <in_Domain.mRID codingScheme="A01">10YFI-1--------U</in_Domain.mRID>
+                <out_Domain.mRID codingScheme="A01">10YFI-1--------U</out_Domain.mRID>
+                <currency_Unit.name>EUR</currency_Unit.name>
+                <price_Measure_Unit.name>MWH</price_Measure_Unit.name>
+                <curveType>A03</curveType>
+                <Period>
+                    <timeInterval>
+                        <start>2024-01-01T23:00Z</start>
+                        <end>2024-01-02T00:00Z</end>
+                    </timeInterval>
+                    <resolution>PT15M</resolution>
+                    <Point>
+                        <position>1</position>
+                        <price.amount>10.5</price.amount>
+                    </Point>
+                    <Point>
+                        <position>3</position>
+                        <price.amount>12</price.amount>
+                    </Point>
+                </Period>
+            </TimeSeries>
+        </Publication_MarketDocument>"#;
+
+        let document = match parse_document(document).unwrap() {
+            EntsoeDocument::Publication(document) => document,
+            other => panic!("Unexpected {}", other.root_element()),
+        };
+
+        let points = document_points(&document).unwrap();
+        let prices: Vec<f32> = points.iter().map(|point| point.price).collect();
+        assert_eq!(prices, vec![10.5, 10.5, 12.0, 12.0]);
+        assert_eq!(points[1].time, Utc.ymd(2024, 1, 1).and_hms(23, 15, 0));
+        assert_eq!(points[0].currency, "EUR");
+    }
+}
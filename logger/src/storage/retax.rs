@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::settings::app_config::AppConfig;
+use crate::settings::config_model::SettingsConfig;
+use crate::storage::price_store::PriceStores;
+
+#[derive(Debug, Serialize)]
+pub struct RetaxReport {
+    pub in_domain: String,
+    pub out_domain: String,
+    /// Number of changed prices by store
+    pub changed: BTreeMap<&'static str, u64>,
+}
+
+/// Re-applies the current settings to the prices already stored for the zone in `[start, end)`
+/// and refreshes the aggregates. The ENTSO-E API isn't called.
+pub async fn retax_prices(
+    config: &AppConfig,
+    stores: &PriceStores,
+    settings: &SettingsConfig,
+    in_domain: &str,
+    out_domain: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<RetaxReport, anyhow::Error> {
+    info!("Recalculating taxes for {}/{} between {} and {}", in_domain, out_domain, start, end);
+
+    let settings = settings
+        .for_zone(in_domain, out_domain)
+        .with_default_timezone(config.timezone);
+
+    let results = join_all(
+        stores
+            .iter()
+            .map(|store| store.retax(in_domain, out_domain, start, end, &settings)),
+    )
+    .await;
+
+    let mut changed = BTreeMap::new();
+    for (store, result) in stores.iter().zip(results) {
+        let count = result?;
+        info!("Taxes recalculated, {} prices changed in {}", count, store.name());
+        changed.insert(store.name(), count);
+    }
+
+    stores.after_write().await?;
+
+    Ok(RetaxReport {
+        in_domain: in_domain.to_string(),
+        out_domain: out_domain.to_string(),
+        changed,
+    })
+}
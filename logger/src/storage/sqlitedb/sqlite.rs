@@ -0,0 +1,308 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::settings::app_config::SqliteConfig;
+use crate::settings::config_model::SettingsConfig;
+use crate::storage::price_store::{PricePoint, PriceStore, StoredPrice};
+
+/// Same columns as the TimescaleDB table. Times are stored as UTC text, which sorts chronologically.
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS day_ahead_prices (
+    time TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    in_domain TEXT NOT NULL,
+    out_domain TEXT NOT NULL,
+    price REAL NOT NULL,
+    measure_unit TEXT NOT NULL,
+    source TEXT NULL DEFAULT NULL,
+    tax_percentage REAL NOT NULL DEFAULT 24,
+    margin REAL NOT NULL DEFAULT 0,
+    electricity_tax REAL NOT NULL DEFAULT 0,
+    transfer_fee REAL NOT NULL DEFAULT 0,
+    UNIQUE (time, in_domain, out_domain)
+);";
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+pub struct SqliteStore {
+    config: SqliteConfig,
+    /// Opened, and the schema created, on first use so a missing file is retried on the next call
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+
+impl SqliteStore {
+    pub fn new(config: SqliteConfig) -> SqliteStore {
+        SqliteStore {
+            config,
+            connection: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Runs the blocking SQLite calls on the blocking thread pool, one at a time on the shared connection.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, anyhow::Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, anyhow::Error> + Send + 'static,
+    {
+        let path = self.config.path.clone();
+        let shared = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            // A call that panicked took the connection with it, the next one opens a new connection
+            let mut shared = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let mut connection = match shared.take() {
+                Some(connection) => connection,
+                None => connect_to_db(&path)?,
+            };
+            let result = f(&mut connection);
+            *shared = Some(connection);
+            result
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl PriceStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    async fn upsert_points(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        points: &[PricePoint],
+        settings: &SettingsConfig,
+    ) -> Result<(), anyhow::Error> {
+        let mut rows = Vec::new();
+        for point in points.iter() {
+            rows.push((point.clone(), settings.consumer_price(point.time, point.price)?));
+        }
+        let in_domain = in_domain.to_string();
+        let out_domain = out_domain.to_string();
+
+        self.with_connection(move |connection| {
+            let trans = connection.transaction()?;
+            {
+                let mut statement = trans.prepare(
+                    "INSERT INTO day_ahead_prices (time, currency, in_domain, out_domain, price, measure_unit, source, tax_percentage, margin, electricity_tax, transfer_fee)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'entsoe', ?7, ?8, ?9, ?10)
+                        ON CONFLICT (time, in_domain, out_domain) DO UPDATE
+                            SET currency = ?2, price = ?5, measure_unit = ?6, source = 'entsoe', tax_percentage = ?7, margin = ?8, electricity_tax = ?9, transfer_fee = ?10",
+                )?;
+                for (point, consumer_price) in rows.iter() {
+                    statement.execute(params![
+                        format_time(&point.time),
+                        point.currency,
+                        in_domain,
+                        out_domain,
+                        point.price,
+                        point.measure_unit,
+                        consumer_price.tax_percentage,
+                        consumer_price.margin,
+                        consumer_price.electricity_tax,
+                        consumer_price.transfer_fee
+                    ])?;
+                }
+            }
+            trans.commit()?;
+
+            info!("SQLite | {} prices written for {}/{}", rows.len(), in_domain, out_domain);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn latest_time(&self, in_domain: &str, out_domain: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        let in_domain = in_domain.to_string();
+        let out_domain = out_domain.to_string();
+
+        self.with_connection(move |connection| {
+            let time: Option<String> = connection
+                .query_row(
+                    "SELECT time FROM day_ahead_prices WHERE in_domain = ?1 AND out_domain = ?2 ORDER BY time DESC LIMIT 1",
+                    params![in_domain, out_domain],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(time.and_then(|time| parse_time(&time)))
+        })
+        .await
+    }
+
+    async fn read_range(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<StoredPrice>, anyhow::Error> {
+        let in_domain = in_domain.to_string();
+        let out_domain = out_domain.to_string();
+
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT time, price, tax_percentage, margin, electricity_tax, transfer_fee FROM day_ahead_prices
+                    WHERE in_domain = ?1 AND out_domain = ?2 AND time >= ?3 AND time < ?4 ORDER BY time",
+            )?;
+            let rows = statement.query_map(
+                params![in_domain, out_domain, format_time(&start), format_time(&end)],
+                |row| {
+                    let time = row.get_ref(0)?.as_str()?;
+                    Ok(StoredPrice {
+                        time: parse_time(time).ok_or_else(|| {
+                            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, format!("Invalid time {}", time).into())
+                        })?,
+                        price: row.get(1)?,
+                        tax_percentage: Some(row.get(2)?),
+                        margin: Some(row.get(3)?),
+                        electricity_tax: Some(row.get(4)?),
+                        transfer_fee: Some(row.get(5)?),
+                    })
+                },
+            )?;
+            let prices = rows.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(prices)
+        })
+        .await
+    }
+
+    async fn retax(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        settings: &SettingsConfig,
+    ) -> Result<u64, anyhow::Error> {
+        let mut changed_rows = Vec::new();
+        for stored in self.read_range(in_domain, out_domain, start, end).await? {
+            let consumer_price = settings.consumer_price(stored.time, stored.price)?;
+            let components = [consumer_price.tax_percentage, consumer_price.margin, consumer_price.electricity_tax, consumer_price.transfer_fee];
+            let stored_components = [stored.tax_percentage, stored.margin, stored.electricity_tax, stored.transfer_fee];
+            if stored_components != components.map(Some) {
+                changed_rows.push((stored.time, components));
+            }
+        }
+        let in_domain = in_domain.to_string();
+        let out_domain = out_domain.to_string();
+
+        self.with_connection(move |connection| {
+            let trans = connection.transaction()?;
+            let mut changed = 0;
+            for (time, components) in changed_rows.iter() {
+                changed += trans.execute(
+                    "UPDATE day_ahead_prices SET tax_percentage = ?4, margin = ?5, electricity_tax = ?6, transfer_fee = ?7
+                        WHERE time = ?1 AND in_domain = ?2 AND out_domain = ?3",
+                    params![format_time(time), in_domain, out_domain, components[0], components[1], components[2], components[3]],
+                )? as u64;
+            }
+            trans.commit()?;
+
+            Ok(changed)
+        })
+        .await
+    }
+
+    async fn health(&self) -> Result<(), anyhow::Error> {
+        self.with_connection(|connection| {
+            connection.query_row("SELECT 1", [], |_| Ok(()))?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn connect_to_db(path: &str) -> Result<Connection, anyhow::Error> {
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let connection = Connection::open(path)?;
+    // Wait for other processes writing to the same file instead of failing
+    connection.busy_timeout(Duration::from_secs(10))?;
+    connection.execute_batch(CREATE_TABLES)?;
+
+    Ok(connection)
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, TIME_FORMAT)
+        .ok()
+        .map(|time| DateTime::from_utc(time, Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn point(hour: u32, price: f32) -> PricePoint {
+        PricePoint {
+            time: Utc.ymd(2024, 1, 1).and_hms(hour, 0, 0),
+            price,
+            document_type: "A44".to_string(),
+            currency: "EUR".to_string(),
+            measure_unit: "MWH".to_string(),
+            curve_type: "A03".to_string(),
+            resolution: chrono::Duration::hours(1),
+        }
+    }
+
+    fn settings(tax_percentage: f32) -> SettingsConfig {
+        serde_yaml::from_str(&format!(
+            "settings:\n  - start_time: \"2020-01-01T00:00:00\"\n    tax_percentage: {}\n",
+            tax_percentage
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upsert_and_retax() {
+        let path = std::env::temp_dir().join(format!("entsoe-logger-sqlite-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = SqliteStore::new(SqliteConfig {
+            enabled: true,
+            path: path.to_string_lossy().to_string(),
+        });
+        let (in_domain, out_domain) = ("10YFI-1--------U", "10YFI-1--------U");
+
+        assert_eq!(store.latest_time(in_domain, out_domain).await.unwrap(), None);
+
+        store.upsert_points(in_domain, out_domain, &[point(0, 10.0), point(1, 20.0)], &settings(24.0)).await.unwrap();
+        // Upserting the same hour replaces the price
+        store.upsert_points(in_domain, out_domain, &[point(1, 25.0), point(2, 30.0)], &settings(24.0)).await.unwrap();
+
+        assert_eq!(store.latest_time(in_domain, out_domain).await.unwrap(), Some(Utc.ymd(2024, 1, 1).and_hms(2, 0, 0)));
+        assert_eq!(store.latest_time(in_domain, "10YDK-1--------W").await.unwrap(), None);
+
+        let start = Utc.ymd(2024, 1, 1).and_hms(0, 0, 0);
+        let end = Utc.ymd(2024, 1, 1).and_hms(2, 0, 0);
+        let prices = store.read_range(in_domain, out_domain, start, end).await.unwrap();
+        assert_eq!(prices.iter().map(|price| price.price).collect::<Vec<f32>>(), vec![10.0, 25.0]);
+        assert_eq!(prices[0].tax_percentage, Some(24.0));
+
+        assert_eq!(store.retax(in_domain, out_domain, start, end, &settings(25.5)).await.unwrap(), 2);
+        assert_eq!(store.retax(in_domain, out_domain, start, end, &settings(25.5)).await.unwrap(), 0);
+        let prices = store.read_range(in_domain, out_domain, start, end + chrono::Duration::hours(1)).await.unwrap();
+        assert_eq!(prices.iter().map(|price| price.tax_percentage.unwrap()).collect::<Vec<f32>>(), vec![25.5, 25.5, 24.0]);
+
+        store.health().await.unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
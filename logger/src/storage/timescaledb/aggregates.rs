@@ -0,0 +1,235 @@
+use api::Area;
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Daily, monthly and yearly averages, named `average_kwh_price_<unit>_by_<unit>_<zone>`.
+pub const UNITS: [&str; 3] = ["day", "month", "year"];
+
+/// PostgreSQL truncates longer identifiers, and the truncated view would never match its name again.
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// Part of a view to recompute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewRefresh {
+    pub view: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Average price views of one zone, bucketed in the zone's local time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneAggregates {
+    pub in_domain: String,
+    pub out_domain: String,
+    pub timezone: Tz,
+}
+
+impl ZoneAggregates {
+    pub fn new(in_domain: &str, out_domain: &str, timezone: Tz) -> ZoneAggregates {
+        ZoneAggregates {
+            in_domain: in_domain.to_string(),
+            out_domain: out_domain.to_string(),
+            timezone,
+        }
+    }
+
+    /// Area code when there's one, e.g. `fi` or `se3`, otherwise the EIC code. Both domains are
+    /// included if they differ, or a hash of them if the view names would get too long.
+    pub fn suffix(&self) -> String {
+        let name = |domain: &str| {
+            let name = Area::find(domain).map_or(domain, |area| area.code).to_ascii_lowercase();
+            name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>()
+        };
+
+        let suffix = if self.in_domain == self.out_domain {
+            name(&self.in_domain)
+        } else {
+            format!("{}_{}", name(&self.in_domain), name(&self.out_domain))
+        };
+        let longest_prefix = UNITS.iter().map(|unit| view_prefix(unit).len()).max().unwrap_or_default();
+        if longest_prefix + suffix.len() <= MAX_IDENTIFIER_LENGTH {
+            suffix
+        } else {
+            format!("{:016x}", fnv1a(&format!("{}/{}", self.in_domain, self.out_domain)))
+        }
+    }
+
+    pub fn view_names(&self) -> Vec<String> {
+        UNITS
+            .iter()
+            .map(|unit| format!("{}{}", view_prefix(unit), self.suffix()))
+            .collect()
+    }
+
+    /// Windows covering the buckets of the prices from `first` to `last`.
+    pub fn refreshes(&self, first: DateTime<Utc>, last: DateTime<Utc>) -> Vec<ViewRefresh> {
+        UNITS
+            .iter()
+            .zip(self.view_names())
+            .map(|(unit, view)| {
+                let (start, end) = refresh_window(unit, self.timezone, first, last);
+                ViewRefresh { view, start, end }
+            })
+            .collect()
+    }
+
+    /// Creates the views if they don't exist, as continuous aggregates on TimescaleDB and as materialized
+    /// views on plain PostgreSQL. Both are refreshed after the writes, there are no refresh policies.
+    pub fn create_sql(&self, timescale: bool) -> String {
+        let timezone = sql_string(self.timezone.name());
+        let mut statements = Vec::new();
+
+        for (unit, view) in UNITS.iter().zip(self.view_names()) {
+            let (options, bucket) = if timescale {
+                (
+                    " WITH (timescaledb.continuous)",
+                    format!("time_bucket('1 {}', time, {})", unit, timezone),
+                )
+            } else {
+                (
+                    "",
+                    format!("date_trunc('{}', time AT TIME ZONE {}) AT TIME ZONE {}", unit, timezone, timezone),
+                )
+            };
+
+            statements.push(format!(
+                "CREATE MATERIALIZED VIEW IF NOT EXISTS {view}{options} AS
+SELECT {bucket} AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = {in_domain} AND out_domain = {out_domain}
+GROUP BY date
+WITH NO DATA;",
+                view = view,
+                options = options,
+                bucket = bucket,
+                in_domain = sql_string(&self.in_domain),
+                out_domain = sql_string(&self.out_domain),
+            ));
+        }
+
+        statements.join("\n")
+    }
+}
+
+/// Widens `[first, last]` to whole local buckets. TimescaleDB only refreshes the buckets that fit
+/// entirely in the window, so a window inside a bucket would refresh nothing.
+pub fn refresh_window(unit: &str, timezone: Tz, first: DateTime<Utc>, last: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = bucket_date(unit, first.with_timezone(&timezone).date().naive_local());
+    let end = bucket_date(unit, last.with_timezone(&timezone).date().naive_local());
+    let end = match unit {
+        "day" => end.succ(),
+        "month" if end.month() == 12 => NaiveDate::from_ymd(end.year() + 1, 1, 1),
+        "month" => NaiveDate::from_ymd(end.year(), end.month() + 1, 1),
+        _ => NaiveDate::from_ymd(end.year() + 1, 1, 1),
+    };
+
+    (local_midnight(timezone, start), local_midnight(timezone, end))
+}
+
+fn bucket_date(unit: &str, date: NaiveDate) -> NaiveDate {
+    match unit {
+        "day" => date,
+        "month" => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        _ => NaiveDate::from_ymd(date.year(), 1, 1),
+    }
+}
+
+pub fn local_midnight(timezone: Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms(0, 0, 0);
+    timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+}
+
+fn view_prefix(unit: &str) -> String {
+    format!("average_kwh_price_{}_by_{}_", unit, unit)
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the std hashers, so the views keep their names.
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_names() {
+        let fi = ZoneAggregates::new("10YFI-1--------U", "10YFI-1--------U", chrono_tz::Europe::Helsinki);
+        assert_eq!(
+            fi.view_names(),
+            vec![
+                "average_kwh_price_day_by_day_fi",
+                "average_kwh_price_month_by_month_fi",
+                "average_kwh_price_year_by_year_fi"
+            ]
+        );
+
+        let unknown = ZoneAggregates::new("10YFI-1--------U", "10Y1001A1001A99Z", chrono_tz::UTC);
+        assert_eq!(unknown.suffix(), "fi_10y1001a1001a99z");
+
+        // Two EIC codes would make the names longer than PostgreSQL keeps
+        let unknown = ZoneAggregates::new("10Y1001A1001A98Y", "10Y1001A1001A99Z", chrono_tz::UTC);
+        let reversed = ZoneAggregates::new("10Y1001A1001A99Z", "10Y1001A1001A98Y", chrono_tz::UTC);
+        assert_eq!(unknown.suffix().len(), 16);
+        assert_ne!(unknown.suffix(), reversed.suffix());
+        for view in unknown.view_names().iter().chain(fi.view_names().iter()) {
+            assert!(view.len() <= MAX_IDENTIFIER_LENGTH, "{}", view);
+        }
+    }
+
+    #[test]
+    fn test_create_sql_buckets_in_the_zone_time_zone() {
+        let se3 = ZoneAggregates::new("10Y1001A1001A46L", "10Y1001A1001A46L", chrono_tz::Europe::Stockholm);
+
+        let sql = se3.create_sql(true);
+        assert!(sql.contains("time_bucket('1 month', time, 'Europe/Stockholm')"), "{}", sql);
+        assert!(sql.contains("WHERE in_domain = '10Y1001A1001A46L' AND out_domain = '10Y1001A1001A46L'"), "{}", sql);
+        assert!(sql.contains("CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_year_by_year_se3 WITH (timescaledb.continuous)"), "{}", sql);
+        assert!(!sql.contains("add_continuous_aggregate_policy"), "{}", sql);
+
+        let sql = se3.create_sql(false);
+        assert!(sql.contains("date_trunc('year', time AT TIME ZONE 'Europe/Stockholm') AT TIME ZONE 'Europe/Stockholm'"), "{}", sql);
+        assert!(!sql.contains("timescaledb"), "{}", sql);
+    }
+
+    #[test]
+    fn test_refresh_window_is_aligned_to_local_buckets() {
+        let helsinki = chrono_tz::Europe::Helsinki;
+        // 2024-12-31 23:00 to 2025-01-01 22:00 in Helsinki
+        let first = Utc.ymd(2024, 12, 31).and_hms(21, 0, 0);
+        let last = Utc.ymd(2025, 1, 1).and_hms(20, 0, 0);
+
+        assert_eq!(
+            refresh_window("day", helsinki, first, last),
+            (Utc.ymd(2024, 12, 30).and_hms(22, 0, 0), Utc.ymd(2025, 1, 1).and_hms(22, 0, 0))
+        );
+        assert_eq!(
+            refresh_window("month", helsinki, first, last),
+            (Utc.ymd(2024, 11, 30).and_hms(22, 0, 0), Utc.ymd(2025, 1, 31).and_hms(22, 0, 0))
+        );
+        assert_eq!(
+            refresh_window("year", helsinki, first, last),
+            (Utc.ymd(2023, 12, 31).and_hms(22, 0, 0), Utc.ymd(2025, 12, 31).and_hms(22, 0, 0))
+        );
+
+        // A single price still covers its whole bucket, across the change to summer time
+        let first = Utc.ymd(2025, 3, 30).and_hms(12, 0, 0);
+        assert_eq!(
+            refresh_window("day", helsinki, first, first),
+            (Utc.ymd(2025, 3, 29).and_hms(22, 0, 0), Utc.ymd(2025, 3, 30).and_hms(21, 0, 0))
+        );
+    }
+}
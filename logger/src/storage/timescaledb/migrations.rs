@@ -0,0 +1,129 @@
+use tokio_postgres::{Client, Error};
+
+/// Schema change, applied once and recorded in `schema_migrations`.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    /// Statements for plain PostgreSQL, empty when there's nothing to do without TimescaleDB
+    pub postgres: &'static str,
+    /// Statements when the TimescaleDB extension is installed
+    pub timescale: &'static str,
+}
+
+impl Migration {
+    pub fn sql(&self, timescale: bool) -> &'static str {
+        if timescale {
+            self.timescale
+        } else {
+            self.postgres
+        }
+    }
+}
+
+const CREATE_DAY_AHEAD_PRICES: &str = include_str!("migrations/0001_create_day_ahead_prices.sql");
+const ADD_PRICE_COMPONENTS: &str = include_str!("migrations/0003_add_price_components.sql");
+const ADD_ZONE_TIME_INDEX: &str = include_str!("migrations/0004_add_zone_time_index.sql");
+
+/// In the order they are applied. Every statement must be idempotent, so that the migrations can be
+/// applied on top of a schema created by hand with the scripts.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_day_ahead_prices",
+        postgres: CREATE_DAY_AHEAD_PRICES,
+        timescale: CREATE_DAY_AHEAD_PRICES,
+    },
+    Migration {
+        version: 2,
+        name: "create_hypertable",
+        postgres: "",
+        timescale: include_str!("migrations/0002_create_hypertable.timescale.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "add_price_components",
+        postgres: ADD_PRICE_COMPONENTS,
+        timescale: ADD_PRICE_COMPONENTS,
+    },
+    Migration {
+        version: 4,
+        name: "add_zone_time_index",
+        postgres: ADD_ZONE_TIME_INDEX,
+        timescale: ADD_ZONE_TIME_INDEX,
+    },
+    Migration {
+        version: 5,
+        name: "create_average_views",
+        postgres: include_str!("migrations/0005_create_average_views.postgres.sql"),
+        timescale: include_str!("migrations/0005_create_average_views.timescale.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "remove_view_policies",
+        postgres: "",
+        timescale: include_str!("migrations/0006_remove_view_policies.timescale.sql"),
+    },
+];
+
+/// Key of the advisory lock held while migrating, so that loggers starting at the same time
+/// don't apply the same migration twice.
+const MIGRATION_LOCK: i64 = 0x656e74736f65;
+
+/// Applies the migrations missing from `schema_migrations`, each in its own transaction.
+pub async fn migrate(client: &mut Client, timescale: bool) -> Result<Vec<&'static Migration>, Error> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+            )",
+        )
+        .await?;
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter() {
+        let trans = client.transaction().await?;
+        trans.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK]).await?;
+
+        let done = trans
+            .query_opt("SELECT 1 FROM schema_migrations WHERE version = $1", &[&migration.version])
+            .await?
+            .is_some();
+        if done {
+            continue;
+        }
+
+        let sql = migration.sql(timescale);
+        if !sql.trim().is_empty() {
+            trans.batch_execute(sql).await?;
+        }
+        trans
+            .execute(
+                "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                &[&migration.version, &migration.name],
+            )
+            .await?;
+        trans.commit().await?;
+
+        info!("Applied migration {:04} {}", migration.version, migration.name);
+        applied.push(migration);
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version, "{} before {}", pair[0].name, pair[1].name);
+        }
+        for migration in MIGRATIONS.iter() {
+            assert!(!migration.timescale.trim().is_empty(), "{}", migration.name);
+        }
+    }
+}
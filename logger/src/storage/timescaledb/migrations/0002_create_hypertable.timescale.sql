@@ -0,0 +1 @@
+SELECT create_hypertable('day_ahead_prices', 'time', if_not_exists => TRUE, migrate_data => TRUE);
@@ -0,0 +1,4 @@
+-- The components are in c/kWh without VAT
+ALTER TABLE day_ahead_prices ADD COLUMN IF NOT EXISTS "margin" REAL NOT NULL DEFAULT '0';
+ALTER TABLE day_ahead_prices ADD COLUMN IF NOT EXISTS "electricity_tax" REAL NOT NULL DEFAULT '0';
+ALTER TABLE day_ahead_prices ADD COLUMN IF NOT EXISTS "transfer_fee" REAL NOT NULL DEFAULT '0';
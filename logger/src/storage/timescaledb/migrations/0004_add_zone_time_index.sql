@@ -0,0 +1,2 @@
+-- For the latest time and range lookups of a zone
+CREATE INDEX IF NOT EXISTS day_ahead_prices_zone_time_idx ON day_ahead_prices (in_domain, out_domain, time DESC);
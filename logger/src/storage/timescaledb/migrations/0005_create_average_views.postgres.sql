@@ -0,0 +1,30 @@
+-- Filled by the refresh after the next fetch
+CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_day_by_day AS
+SELECT date_trunc('day', time AT TIME ZONE 'Europe/Helsinki') AT TIME ZONE 'Europe/Helsinki' AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY date
+WITH NO DATA;
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_month_by_month AS
+SELECT date_trunc('month', time AT TIME ZONE 'Europe/Helsinki') AT TIME ZONE 'Europe/Helsinki' AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY date
+WITH NO DATA;
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_year_by_year AS
+SELECT date_trunc('year', time AT TIME ZONE 'Europe/Helsinki') AT TIME ZONE 'Europe/Helsinki' AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY date
+WITH NO DATA;
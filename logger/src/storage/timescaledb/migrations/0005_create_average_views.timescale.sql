@@ -0,0 +1,39 @@
+-- Filled by the refresh after the next fetch
+CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_day_by_day
+    WITH (timescaledb.continuous) AS
+SELECT time_bucket('1 day', time, 'Europe/Helsinki') AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY date
+WITH NO DATA;
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_month_by_month
+    WITH (timescaledb.continuous) AS
+SELECT time_bucket('1 month', time, 'Europe/Helsinki') AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY date
+WITH NO DATA;
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS average_kwh_price_year_by_year
+    WITH (timescaledb.continuous) AS
+SELECT time_bucket('1 year', time, 'Europe/Helsinki') AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM day_ahead_prices
+WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY date
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('average_kwh_price_day_by_day',
+    start_offset => NULL,
+    end_offset => NULL,
+    schedule_interval => INTERVAL '1 hour',
+    if_not_exists => TRUE);
@@ -0,0 +1,4 @@
+-- The policies refreshed the whole history, the logger refreshes the buckets of the changed prices instead
+SELECT remove_continuous_aggregate_policy('average_kwh_price_day_by_day', if_exists => TRUE);
+SELECT remove_continuous_aggregate_policy('average_kwh_price_month_by_month', if_exists => TRUE);
+SELECT remove_continuous_aggregate_policy('average_kwh_price_year_by_year', if_exists => TRUE);
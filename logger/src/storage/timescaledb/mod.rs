@@ -1 +1,3 @@
-pub mod timescale;
\ No newline at end of file
+pub mod aggregates;
+pub mod migrations;
+pub mod timescale;
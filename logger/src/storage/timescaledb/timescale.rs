@@ -1,156 +1,467 @@
-use std::str::FromStr;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
 
-use api::PublicationMarketDocument;
-use chrono::{Duration as ChronoDuration, Utc};
-use tokio_postgres::{Error, NoTls};
-use iso8601_duration::Duration as IsoDuration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 
-use crate::settings::config_model::SettingsConfig;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::sync::OnceCell;
+use tokio_postgres::config::SslMode;
+use tokio_postgres::{Client, Error, NoTls, Transaction};
+use tokio_postgres_rustls::MakeRustlsConnect;
 
-pub fn is_enabled() -> bool {
-    dotenv::var("TIMESCALEDB_ENABLED")
-        .map(|var| var.parse::<bool>())
-        .unwrap_or(Ok(false))
-        .unwrap()
+use super::aggregates::{refresh_window, ViewRefresh, ZoneAggregates, UNITS};
+use super::migrations;
+use crate::settings::app_config::TimescaleDbConfig;
+use crate::settings::config_model::{ConsumerPrice, SettingsConfig};
+use crate::storage::price_store::{PricePoint, PriceStore, StoredPrice};
+
+/// Rows per upsert statement. The columns are sent as arrays, so this isn't bound by the parameter limit.
+const BATCH_SIZE: usize = 5000;
+
+/// Aggregates refreshed after the writes, created by `scripts/views.sql` or `scripts/views_postgres.sql`.
+const VIEWS: [&str; 3] = [
+    "average_kwh_price_day_by_day",
+    "average_kwh_price_month_by_month",
+    "average_kwh_price_year_by_year",
+];
+
+/// The `VIEWS` average Finnish prices by the local day.
+const VIEWS_DOMAIN: &str = "10YFI-1--------U";
+const VIEWS_TIMEZONE: Tz = chrono_tz::Europe::Helsinki;
+
+/// First and last changed price time per zone.
+type ChangedRanges = HashMap<(String, String), (DateTime<Utc>, DateTime<Utc>)>;
+
+/// PostgreSQL store, with or without the TimescaleDB extension.
+pub struct TimescaleStore {
+    pool: Pool,
+    /// Per-zone aggregates, created after the migrations and refreshed after the writes.
+    zones: Vec<ZoneAggregates>,
+    /// Prices changed since the last refresh of the views.
+    changed: Mutex<ChangedRanges>,
+    /// Whether the database has the TimescaleDB extension, detected on first use.
+    timescale: OnceCell<bool>,
 }
 
-pub async fn upsert_document_into_timescaledb(
-    document: &PublicationMarketDocument,
-    in_domain: &str,
-    out_domain: &str,
-    settings: &SettingsConfig,
-) -> Result<(), Error> {
-    if !is_enabled() {
-        return Ok(());
-    }
-
-    let mut messages = Vec::new();
-
-    let mut client = connect_to_db().await?;
-    let trans = client.transaction().await?;
-    for time_serie in document.time_series.iter() {
-        for period in time_serie.period.iter() {
-            let start = &period.time_interval.start_as_utc();
-            let end = &period.time_interval.end_as_utc();
-            if start.is_none() || end.is_none() {
-                messages.push(
-                    "TimescaleDB | Skipping logging because start or end time couldn't be parsed"
-                        .to_string(),
+impl TimescaleStore {
+    /// Creates the connection pool. The connections are opened when first needed.
+    pub fn new(config: TimescaleDbConfig, zones: Vec<ZoneAggregates>) -> Result<TimescaleStore, anyhow::Error> {
+        Ok(TimescaleStore {
+            pool: create_pool(&config)?,
+            zones,
+            changed: Mutex::new(HashMap::new()),
+            timescale: OnceCell::new(),
+        })
+    }
+
+    async fn has_timescale(&self, client: &Client) -> Result<bool, Error> {
+        let timescale = self
+            .timescale
+            .get_or_try_init(|| async {
+                let row = client
+                    .query_one("SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb')", &[])
+                    .await?;
+                let timescale: bool = row.get(0);
+                info!(
+                    "TimescaleDB extension {}, using {}",
+                    if timescale { "found" } else { "not found" },
+                    if timescale { "continuous aggregates" } else { "plain materialized views" }
                 );
+                Ok(timescale)
+            })
+            .await?;
+
+        Ok(*timescale)
+    }
+
+    fn mark_changed(&self, in_domain: &str, out_domain: &str, times: &[DateTime<Utc>]) {
+        let (first, last) = match (times.iter().min(), times.iter().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return,
+        };
+
+        let mut changed = self.changed.lock().unwrap();
+        let range = changed
+            .entry((in_domain.to_string(), out_domain.to_string()))
+            .or_insert((first, last));
+        *range = (range.0.min(first), range.1.max(last));
+    }
+
+    /// The views to refresh for the changed prices, each over the buckets the prices fall in.
+    fn refreshes(&self, changed: &ChangedRanges) -> Vec<ViewRefresh> {
+        let mut refreshes = Vec::new();
+
+        for ((in_domain, out_domain), (first, last)) in changed.iter() {
+            for zone in self.zones.iter() {
+                if &zone.in_domain == in_domain && &zone.out_domain == out_domain {
+                    refreshes.extend(zone.refreshes(*first, *last));
+                }
+            }
+        }
+
+        if let Some((first, last)) = changed.get(&(VIEWS_DOMAIN.to_string(), VIEWS_DOMAIN.to_string())) {
+            for (unit, view) in UNITS.iter().zip(VIEWS.iter()) {
+                let (start, end) = refresh_window(unit, VIEWS_TIMEZONE, *first, *last);
+                refreshes.push(ViewRefresh {
+                    view: view.to_string(),
+                    start,
+                    end,
+                });
+            }
+        }
+
+        refreshes
+    }
+}
+
+#[async_trait]
+impl PriceStore for TimescaleStore {
+    fn name(&self) -> &'static str {
+        "TimescaleDB"
+    }
+
+    async fn upsert_points(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        points: &[PricePoint],
+        settings: &SettingsConfig,
+    ) -> Result<(), anyhow::Error> {
+        // One row per time, a multi-row upsert can't update the same row twice
+        let mut rows = BTreeMap::new();
+        for point in points.iter() {
+            rows.insert(point.time, (point, settings.consumer_price(point.time, point.price)?));
+        }
+        let rows: Vec<(&PricePoint, ConsumerPrice)> = rows.into_values().collect();
+
+        let mut client = self.pool.get().await?;
+        let trans = client.transaction().await?;
+        let mut changed = Vec::new();
+        for batch in rows.chunks(BATCH_SIZE) {
+            changed.extend(upsert_batch(&trans, in_domain, out_domain, batch).await?);
+        }
+        trans.commit().await?;
+        self.mark_changed(in_domain, out_domain, &changed);
+
+        info!(
+            "TimescaleDB | {} prices written for {}/{}, {} changed",
+            rows.len(),
+            in_domain,
+            out_domain,
+            changed.len()
+        );
+
+        Ok(())
+    }
+
+    async fn latest_time(&self, in_domain: &str, out_domain: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt("SELECT time FROM day_ahead_prices WHERE in_domain = $1 AND out_domain = $2 ORDER BY time DESC LIMIT 1", &[&in_domain, &out_domain])
+            .await?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn read_range(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<StoredPrice>, anyhow::Error> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query("SELECT time, price, tax_percentage, margin, electricity_tax, transfer_fee FROM day_ahead_prices
+                        WHERE in_domain = $1 AND out_domain = $2 AND time >= $3 AND time < $4 ORDER BY time",
+                &[&in_domain, &out_domain, &start, &end])
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| StoredPrice {
+                time: row.get(0),
+                price: row.get(1),
+                tax_percentage: Some(row.get(2)),
+                margin: Some(row.get(3)),
+                electricity_tax: Some(row.get(4)),
+                transfer_fee: Some(row.get(5)),
+            })
+            .collect())
+    }
+
+    async fn retax(
+        &self,
+        in_domain: &str,
+        out_domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        settings: &SettingsConfig,
+    ) -> Result<u64, anyhow::Error> {
+        let stored_prices = self.read_range(in_domain, out_domain, start, end).await?;
+
+        let mut client = self.pool.get().await?;
+        let trans = client.transaction().await?;
+        let mut changed = Vec::new();
+        for stored in stored_prices.iter() {
+            let consumer_price = settings.consumer_price(stored.time, stored.price)?;
+            let components = [consumer_price.tax_percentage, consumer_price.margin, consumer_price.electricity_tax, consumer_price.transfer_fee];
+            let stored_components = [stored.tax_percentage, stored.margin, stored.electricity_tax, stored.transfer_fee];
+            if stored_components == components.map(Some) {
                 continue;
             }
-        
-            let parsed_duration = IsoDuration::from_str(&period.resolution).expect("Failed to parse duration");
-            let resolution = ChronoDuration::seconds(parsed_duration.to_std().unwrap().as_secs() as i64);
-            let mut last_price = None;
-            let mut current_time = start.unwrap();
-        
-            while current_time < end.unwrap() {
-                let position = ((current_time - start.unwrap()).num_seconds() / resolution.num_seconds()) + 1;
-                let point = period.point.iter().find(|p| p.position == position as i32);
-                let price = if let Some(point) = point {
-                    last_price = Some(point.price);
-                    point.price
-                } else {
-                    last_price.unwrap_or(0.0)
-                };
-        
-                let tax_percentage: f32 = settings.get_current_tax_percentage(current_time);
-                let _ = trans
-                    .execute("INSERT INTO day_ahead_prices (time, currency, in_domain, out_domain, price, measure_unit, source, tax_percentage) 
-                                        VALUES ($1, $2, $3, $4, $5, $6, 'entsoe', $7)
-                                        ON CONFLICT (time, in_domain, out_domain) DO UPDATE
-                                            SET currency = $2, price = $5, measure_unit = $6, source = 'entsoe', tax_percentage = $7",
-                    &[&current_time, &time_serie.currency_unit.to_string(), &in_domain.to_string(), &out_domain.to_string(), &price, &time_serie.price_measure_unit.to_string(), &tax_percentage])
+
+            let updated = trans
+                .execute("UPDATE day_ahead_prices SET tax_percentage = $4, margin = $5, electricity_tax = $6, transfer_fee = $7
+                            WHERE time = $1 AND in_domain = $2 AND out_domain = $3",
+                    &[&stored.time, &in_domain, &out_domain, &components[0], &components[1], &components[2], &components[3]])
                 .await?;
-        
-                messages.push(format!("TimescaleDB | {} - {:.2}", current_time, price));
-        
-                current_time = current_time + resolution;
+            if updated > 0 {
+                changed.push(stored.time);
             }
         }
-    }
 
-    trans.commit().await?;
+        trans.commit().await?;
+        self.mark_changed(in_domain, out_domain, &changed);
 
-    let all_messages = messages.join("\n");
-    info!("{}", all_messages);
+        Ok(changed.len() as u64)
+    }
 
-    Ok(())
-}
+    async fn health(&self) -> Result<(), anyhow::Error> {
+        let client = self.pool.get().await?;
+        client.execute("SELECT 1", &[]).await?;
 
-pub async fn get_latest_time(in_domain: &str, out_domain: &str) -> Option<chrono::DateTime<Utc>> {
-    let client = connect_to_db().await;
-    if client.is_err() {
-        return None;
+        Ok(())
     }
-    let client = client.unwrap();
 
-    // Now we can execute a simple statement that just returns its parameter.
-    let rows = client
-        .query("SELECT time FROM day_ahead_prices WHERE in_domain = $1 AND out_domain = $2 ORDER BY time DESC LIMIT 1", &[&in_domain, &out_domain])
+    async fn after_write(&self) -> Result<(), anyhow::Error> {
+        let changed = std::mem::take(&mut *self.changed.lock().unwrap());
+        if changed.is_empty() {
+            debug!("TimescaleDB | No prices changed, the views are up to date");
+            return Ok(());
+        }
+
+        let result = async {
+            let client = self.pool.get().await?;
+            let timescale = self.has_timescale(&client).await?;
+            refresh_views(&client, timescale, &self.refreshes(&changed)).await?;
+
+            Ok(())
+        }
         .await;
-    if rows.is_err() {
-        return None;
-    }
-    let rows = rows.unwrap();
-    if rows.len() == 0 {
-        return None;
+
+        // Kept for the next write to retry
+        if result.is_err() {
+            for ((in_domain, out_domain), (first, last)) in changed.iter() {
+                self.mark_changed(in_domain, out_domain, &[*first, *last]);
+            }
+        }
+
+        result
     }
 
-    let value: chrono::DateTime<Utc> = rows[0].get(0);
-    Some(value)
+    async fn migrate(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut client = self.pool.get().await?;
+        let timescale = self.has_timescale(&client).await?;
+        let views: Vec<String> = VIEWS
+            .iter()
+            .map(|view| view.to_string())
+            .chain(self.zones.iter().flat_map(|zone| zone.view_names()))
+            .collect();
+        let existing = existing_views(&client, timescale, &views).await?;
+        let applied = migrations::migrate(&mut client, timescale).await?;
+
+        // Zones may be added to the configuration at any time, so their views aren't versioned
+        for zone in self.zones.iter() {
+            client.batch_execute(&zone.create_sql(timescale)).await?;
+        }
+
+        // New views are created without data and the writes only refresh the changed buckets,
+        // so they are filled once from the stored prices
+        if views.iter().any(|view| !existing.contains(view)) {
+            let domains = self
+                .zones
+                .iter()
+                .map(|zone| (zone.in_domain.clone(), zone.out_domain.clone()))
+                .chain(std::iter::once((VIEWS_DOMAIN.to_string(), VIEWS_DOMAIN.to_string())));
+            let mut stored = ChangedRanges::new();
+            for (in_domain, out_domain) in domains {
+                if let Some(range) = stored_range(&client, &in_domain, &out_domain).await? {
+                    stored.insert((in_domain, out_domain), range);
+                }
+            }
+            let refreshes: Vec<ViewRefresh> = self
+                .refreshes(&stored)
+                .into_iter()
+                .filter(|refresh| !existing.contains(&refresh.view))
+                .collect();
+            refresh_views(&client, timescale, &refreshes).await?;
+        }
+
+        Ok(applied
+            .iter()
+            .map(|migration| format!("{:04} {}", migration.version, migration.name))
+            .collect())
+    }
 }
 
-pub async fn refresh_views() -> Result<(), Error> {
-    let client = connect_to_db().await?;
+/// Upserts the rows with one statement by unnesting the column arrays. Returns the times of the rows
+/// inserted or updated, rows already stored with the same values are left alone.
+async fn upsert_batch(
+    trans: &Transaction<'_>,
+    in_domain: &str,
+    out_domain: &str,
+    rows: &[(&PricePoint, ConsumerPrice)],
+) -> Result<Vec<DateTime<Utc>>, Error> {
+    let times: Vec<DateTime<Utc>> = rows.iter().map(|(point, _)| point.time).collect();
+    let currencies: Vec<&str> = rows.iter().map(|(point, _)| point.currency.as_str()).collect();
+    let prices: Vec<f32> = rows.iter().map(|(point, _)| point.price).collect();
+    let measure_units: Vec<&str> = rows.iter().map(|(point, _)| point.measure_unit.as_str()).collect();
+    let tax_percentages: Vec<f32> = rows.iter().map(|(_, price)| price.tax_percentage).collect();
+    let margins: Vec<f32> = rows.iter().map(|(_, price)| price.margin).collect();
+    let electricity_taxes: Vec<f32> = rows.iter().map(|(_, price)| price.electricity_tax).collect();
+    let transfer_fees: Vec<f32> = rows.iter().map(|(_, price)| price.transfer_fee).collect();
 
-    // Execute the refresh commands
-    client
-        .execute(
-            "CALL refresh_continuous_aggregate('average_kwh_price_day_by_day', NULL, NULL)",
-            &[],
-        )
+    let rows = trans
+        .query("INSERT INTO day_ahead_prices (time, currency, in_domain, out_domain, price, measure_unit, source, tax_percentage, margin, electricity_tax, transfer_fee)
+                    SELECT time, currency, $3::TEXT, $4::TEXT, price, measure_unit, 'entsoe', tax_percentage, margin, electricity_tax, transfer_fee
+                    FROM UNNEST($1::TIMESTAMPTZ[], $2::TEXT[], $5::REAL[], $6::TEXT[], $7::REAL[], $8::REAL[], $9::REAL[], $10::REAL[])
+                        AS rows (time, currency, price, measure_unit, tax_percentage, margin, electricity_tax, transfer_fee)
+                    ON CONFLICT (time, in_domain, out_domain) DO UPDATE
+                        SET currency = EXCLUDED.currency, price = EXCLUDED.price, measure_unit = EXCLUDED.measure_unit, source = EXCLUDED.source,
+                            tax_percentage = EXCLUDED.tax_percentage, margin = EXCLUDED.margin, electricity_tax = EXCLUDED.electricity_tax, transfer_fee = EXCLUDED.transfer_fee
+                        WHERE (day_ahead_prices.currency, day_ahead_prices.price, day_ahead_prices.measure_unit, day_ahead_prices.source,
+                                day_ahead_prices.tax_percentage, day_ahead_prices.margin, day_ahead_prices.electricity_tax, day_ahead_prices.transfer_fee)
+                            IS DISTINCT FROM (EXCLUDED.currency, EXCLUDED.price, EXCLUDED.measure_unit, EXCLUDED.source,
+                                EXCLUDED.tax_percentage, EXCLUDED.margin, EXCLUDED.electricity_tax, EXCLUDED.transfer_fee)
+                    RETURNING time",
+            &[&times, &currencies, &in_domain, &out_domain, &prices, &measure_units, &tax_percentages, &margins, &electricity_taxes, &transfer_fees])
         .await?;
-    client
-        .execute(
-            "CALL refresh_continuous_aggregate('average_kwh_price_month_by_month', NULL, NULL)",
-            &[],
-        )
-        .await?;
-    client
-        .execute(
-            "CALL refresh_continuous_aggregate('average_kwh_price_year_by_year', NULL, NULL)",
-            &[],
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// The given views that have been created, as continuous aggregates or materialized views.
+async fn existing_views(client: &Client, timescale: bool, views: &[String]) -> Result<Vec<String>, Error> {
+    let query = if timescale {
+        "SELECT view_name::TEXT FROM timescaledb_information.continuous_aggregates WHERE view_name = ANY($1)"
+    } else {
+        "SELECT matviewname::TEXT FROM pg_matviews WHERE matviewname = ANY($1)"
+    };
+    let rows = client.query(query, &[&views]).await?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// First and last stored price of the zone.
+async fn stored_range(
+    client: &Client,
+    in_domain: &str,
+    out_domain: &str,
+) -> Result<Option<(DateTime<Utc>, DateTime<Utc>)>, Error> {
+    let row = client
+        .query_one(
+            "SELECT MIN(time), MAX(time) FROM day_ahead_prices WHERE in_domain = $1 AND out_domain = $2",
+            &[&in_domain, &out_domain],
         )
         .await?;
+    let first: Option<DateTime<Utc>> = row.get(0);
+    let last: Option<DateTime<Utc>> = row.get(1);
+
+    Ok(first.zip(last))
+}
+
+/// Refreshes the views that exist. Continuous aggregates are only recomputed within the window,
+/// plain materialized views can only be refreshed as a whole.
+pub async fn refresh_views(client: &Client, timescale: bool, refreshes: &[ViewRefresh]) -> Result<(), Error> {
+    let views: Vec<String> = refreshes.iter().map(|refresh| refresh.view.clone()).collect();
+    let existing = existing_views(client, timescale, &views).await?;
+
+    for view in existing.iter() {
+        if !timescale {
+            client
+                .execute(format!("REFRESH MATERIALIZED VIEW {}", view).as_str(), &[])
+                .await?;
+            continue;
+        }
+
+        for refresh in refreshes.iter().filter(|refresh| &refresh.view == view) {
+            debug!("Refreshing {} from {} to {}", view, refresh.start, refresh.end);
+            client
+                .execute(
+                    format!(
+                        "CALL refresh_continuous_aggregate('{}', '{}'::TIMESTAMPTZ, '{}'::TIMESTAMPTZ)",
+                        view,
+                        refresh.start.to_rfc3339(),
+                        refresh.end.to_rfc3339()
+                    )
+                    .as_str(),
+                    &[],
+                )
+                .await?;
+        }
+    }
 
     Ok(())
 }
 
-async fn connect_to_db() -> Result<tokio_postgres::Client, Error> {
-    let (client, connection) = tokio_postgres::connect(
-        &dotenv::var("TIMESCALEDB_CONNECTION_STRING").unwrap_or(
-            "host=localhost user=myuser password=mysecretpassword dbname=electricity".to_string(),
-        ),
-        NoTls,
-    )
-    .await?;
+/// Connections are checked before they are handed out, so the pool reconnects after a database restart.
+fn create_pool(config: &TimescaleDbConfig) -> Result<Pool, anyhow::Error> {
+    let pg_config: tokio_postgres::Config = config.connection_string.parse()?;
+    let manager_config = || ManagerConfig {
+        recycling_method: RecyclingMethod::Verified,
+    };
 
-    // The connection object performs the actual communication with the database,
-    // so spawn it off to run on its own.
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            error!("connection error: {}", e);
+    let manager = if uses_tls(&pg_config, config) {
+        Manager::from_config(pg_config, tls_connector(config)?, manager_config())
+    } else {
+        Manager::from_config(pg_config, NoTls, manager_config())
+    };
+
+    Ok(Pool::builder(manager).max_size(config.pool_size).build()?)
+}
+
+/// TLS only when it's asked for with `sslmode=require` or a CA certificate. Under the default
+/// `sslmode=prefer` a failed handshake isn't retried in plain text, so a server with a self-signed
+/// certificate couldn't be connected to at all.
+fn uses_tls(pg_config: &tokio_postgres::Config, config: &TimescaleDbConfig) -> bool {
+    match pg_config.get_ssl_mode() {
+        SslMode::Disable => false,
+        SslMode::Require => true,
+        _ => config.ca_certificate.is_some(),
+    }
+}
+
+/// Verifies the server against the bundled Mozilla roots, the images have no system certificate store.
+fn tls_connector(config: &TimescaleDbConfig) -> Result<MakeRustlsConnect, anyhow::Error> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(path) = &config.ca_certificate {
+        for certificate in rustls_pemfile::certs(&mut BufReader::new(File::open(path)?)) {
+            roots.add(certificate?)?;
         }
-    });
+    }
+    let tls = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
 
-    Ok(client)
+    Ok(MakeRustlsConnect::new(tls))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
+    use chrono::{Duration, TimeZone};
+
     use crate::dotenv;
+    use crate::settings::config::load_app_config;
 
     use super::*;
 
@@ -158,10 +469,166 @@ mod tests {
     async fn test_get_latest_time() {
         dotenv().ok();
 
-        let in_domain = dotenv::var("IN_DOMAIN").unwrap();
-        let out_domain = dotenv::var("OUT_DOMAIN").unwrap();
+        let config = load_app_config(format!("configs/{}.yaml", "production")).unwrap();
+        let zone = config.default_zone().unwrap();
 
-        let response = get_latest_time(&in_domain, &out_domain).await;
+        let store = TimescaleStore::new(config.timescaledb.clone(), Vec::new()).unwrap();
+        let response = store.latest_time(&zone.in_domain, &zone.out_domain).await;
         info!("Last time in TimescaleDB is {:?}", response);
     }
+
+    /// The upsert used before the batching, one statement per price.
+    async fn upsert_row_by_row(
+        trans: &Transaction<'_>,
+        in_domain: &str,
+        out_domain: &str,
+        rows: &[(&PricePoint, ConsumerPrice)],
+    ) -> Result<(), Error> {
+        for (point, consumer_price) in rows.iter() {
+            trans
+                .execute("INSERT INTO day_ahead_prices (time, currency, in_domain, out_domain, price, measure_unit, source, tax_percentage, margin, electricity_tax, transfer_fee)
+                            VALUES ($1, $2, $3, $4, $5, $6, 'entsoe', $7, $8, $9, $10)
+                            ON CONFLICT (time, in_domain, out_domain) DO UPDATE
+                                SET currency = $2, price = $5, measure_unit = $6, source = 'entsoe', tax_percentage = $7, margin = $8, electricity_tax = $9, transfer_fee = $10",
+                    &[&point.time, &point.currency, &in_domain, &out_domain, &point.price, &point.measure_unit, &consumer_price.tax_percentage, &consumer_price.margin, &consumer_price.electricity_tax, &consumer_price.transfer_fee])
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Backfill throughput for a year of 15 minute prices, row by row and batched. Needs a database:
+    /// `TIMESCALEDB_CONNECTION_STRING=... cargo test --release bench_backfill -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_backfill_throughput() {
+        dotenv().ok();
+
+        let config = load_app_config(format!("configs/{}.yaml", "production")).unwrap();
+        let (in_domain, out_domain) = ("BENCH-IN", "BENCH-OUT");
+        let settings = config.settings.for_zone(in_domain, out_domain).with_default_timezone(config.timezone);
+        let start = Utc.ymd(2024, 1, 1).and_hms(0, 0, 0);
+        let points: Vec<PricePoint> = (0..365 * 96)
+            .map(|i| PricePoint {
+                time: start + Duration::minutes(15 * i),
+                price: (i % 200) as f32,
+                document_type: "A44".to_string(),
+                currency: "EUR".to_string(),
+                measure_unit: "MWH".to_string(),
+                curve_type: "A03".to_string(),
+                resolution: Duration::minutes(15),
+            })
+            .collect();
+        let rows: Vec<(&PricePoint, ConsumerPrice)> = points
+            .iter()
+            .map(|point| (point, settings.consumer_price(point.time, point.price).unwrap()))
+            .collect();
+
+        let pool = create_pool(&config.timescaledb).unwrap();
+        let mut client = pool.get().await.unwrap();
+        for batched in [false, true].iter().copied() {
+            for existing in [false, true].iter().copied() {
+                if !existing {
+                    client
+                        .execute("DELETE FROM day_ahead_prices WHERE in_domain = $1 AND out_domain = $2", &[&in_domain, &out_domain])
+                        .await
+                        .unwrap();
+                }
+
+                let started = Instant::now();
+                let trans = client.transaction().await.unwrap();
+                if batched {
+                    for batch in rows.chunks(BATCH_SIZE) {
+                        upsert_batch(&trans, in_domain, out_domain, batch).await.unwrap();
+                    }
+                } else {
+                    upsert_row_by_row(&trans, in_domain, out_domain, &rows).await.unwrap();
+                }
+                trans.commit().await.unwrap();
+                let elapsed = started.elapsed();
+
+                println!(
+                    "{} {} {} rows: {:.2} s, {:.0} rows/s",
+                    if batched { "batched" } else { "row by row" },
+                    if existing { "updating" } else { "inserting" },
+                    rows.len(),
+                    elapsed.as_secs_f64(),
+                    rows.len() as f64 / elapsed.as_secs_f64()
+                );
+            }
+        }
+
+        client
+            .execute("DELETE FROM day_ahead_prices WHERE in_domain = $1 AND out_domain = $2", &[&in_domain, &out_domain])
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_refreshes_cover_the_changed_prices() {
+        let fi = ZoneAggregates::new("10YFI-1--------U", "10YFI-1--------U", chrono_tz::Europe::Helsinki);
+        let se3 = ZoneAggregates::new("10Y1001A1001A46L", "10Y1001A1001A46L", chrono_tz::Europe::Stockholm);
+        let store = TimescaleStore::new(TimescaleDbConfig::default(), vec![fi, se3]).unwrap();
+        assert!(store.refreshes(&store.changed.lock().unwrap()).is_empty());
+
+        store.mark_changed("10YFI-1--------U", "10YFI-1--------U", &[Utc.ymd(2025, 1, 2).and_hms(12, 0, 0)]);
+        store.mark_changed("10YFI-1--------U", "10YFI-1--------U", &[]);
+        store.mark_changed("10YFI-1--------U", "10YFI-1--------U", &[Utc.ymd(2025, 1, 1).and_hms(12, 0, 0)]);
+        let refreshes = store.refreshes(&store.changed.lock().unwrap());
+
+        let views: Vec<&str> = refreshes.iter().map(|refresh| refresh.view.as_str()).collect();
+        assert_eq!(
+            views,
+            vec![
+                "average_kwh_price_day_by_day_fi",
+                "average_kwh_price_month_by_month_fi",
+                "average_kwh_price_year_by_year_fi",
+                "average_kwh_price_day_by_day",
+                "average_kwh_price_month_by_month",
+                "average_kwh_price_year_by_year",
+            ]
+        );
+        assert_eq!(refreshes[0].start, Utc.ymd(2024, 12, 31).and_hms(22, 0, 0));
+        assert_eq!(refreshes[0].end, Utc.ymd(2025, 1, 2).and_hms(22, 0, 0));
+
+        // Other zones leave the Finnish views alone
+        let mut changed = ChangedRanges::new();
+        let time = Utc.ymd(2025, 1, 3).and_hms(12, 0, 0);
+        changed.insert(("10Y1001A1001A46L".to_string(), "10Y1001A1001A46L".to_string()), (time, time));
+        let views: Vec<String> = store.refreshes(&changed).into_iter().map(|refresh| refresh.view).collect();
+        assert_eq!(
+            views,
+            vec![
+                "average_kwh_price_day_by_day_se3",
+                "average_kwh_price_month_by_month_se3",
+                "average_kwh_price_year_by_year_se3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tls_only_when_asked_for() {
+        let uses_tls = |connection_string: &str, ca_certificate: Option<&str>| {
+            let config = TimescaleDbConfig {
+                connection_string: connection_string.to_string(),
+                ca_certificate: ca_certificate.map(str::to_string),
+                ..TimescaleDbConfig::default()
+            };
+            uses_tls(&config.connection_string.parse().unwrap(), &config)
+        };
+
+        assert!(!uses_tls("host=localhost", None));
+        assert!(!uses_tls("host=localhost sslmode=prefer", None));
+        assert!(uses_tls("host=localhost sslmode=prefer", Some("ca.pem")));
+        assert!(uses_tls("host=localhost sslmode=require", None));
+        assert!(!uses_tls("host=localhost sslmode=disable", Some("ca.pem")));
+    }
+
+    #[test]
+    fn test_plain_postgres_views_match_refreshed_views() {
+        let script = include_str!("../../../../scripts/views_postgres.sql");
+        for view in VIEWS.iter() {
+            assert!(script.contains(&format!("CREATE MATERIALIZED VIEW {} AS", view)), "{}", view);
+        }
+    }
 }
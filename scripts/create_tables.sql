@@ -7,7 +7,17 @@ CREATE TABLE "day_ahead_prices"(
 	"measure_unit" VARCHAR(3) NOT NULL,
 	"source" TEXT NULL DEFAULT NULL,
 	"tax_percentage" REAL NOT NULL DEFAULT '24',
+	"margin" REAL NOT NULL DEFAULT '0',
+	"electricity_tax" REAL NOT NULL DEFAULT '0',
+	"transfer_fee" REAL NOT NULL DEFAULT '0',
 	UNIQUE (TIME, in_domain, out_domain)
 );
 
-SELECT CREATE_HYPERTABLE('day_ahead_prices', BY_RANGE('time'));
+-- A hypertable when the TimescaleDB extension is available, otherwise a plain PostgreSQL table
+DO $$
+BEGIN
+	IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb') THEN
+		PERFORM CREATE_HYPERTABLE('day_ahead_prices', BY_RANGE('time'));
+	END IF;
+END
+$$;
@@ -5,7 +5,8 @@ CREATE MATERIALIZED VIEW average_kwh_price_day_by_day
     with (timescaledb.continuous) as
 SELECT time_bucket('1 day', time, 'Europe/Helsinki') AS date,
     AVG(price / 10) AS avg_price,
-    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
 FROM day_ahead_prices
 WHERE in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
 GROUP BY date
@@ -20,7 +21,8 @@ CREATE MATERIALIZED VIEW average_kwh_price_month_by_month
 SELECT 
     time_bucket('1 month', time, 'Europe/Helsinki') AS date,
     AVG(price / 10) AS avg_price,
-    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
 FROM 
     day_ahead_prices
 WHERE 
@@ -39,7 +41,8 @@ CREATE MATERIALIZED VIEW average_kwh_price_year_by_year
 SELECT
     time_bucket('1 year', time, 'Europe/Helsinki') AS date,
     AVG(price / 10) AS avg_price,
-    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
 FROM
     day_ahead_prices
 WHERE
@@ -52,11 +55,7 @@ ORDER BY
 -- To drop the view for the yearly averages in day-ahead prices, run:
 -- DROP MATERIALIZED VIEW average_kwh_price_year_by_year;
 
--- Create a continuous aggregate policy for the view
-SELECT add_continuous_aggregate_policy('average_kwh_price_day_by_day',
-   start_offset => NULL,
-   end_offset => NULL,
-   schedule_interval => INTERVAL '1 hour');
+-- The logger refreshes the buckets of the prices it writes, so the views need no refresh policy
 
 -- Manual refresh for the views
 -- CALL refresh_continuous_aggregate('average_kwh_price_day_by_day', NULL, NULL);
@@ -0,0 +1,60 @@
+-- Description: Create materialized views for a PostgreSQL database without the TimescaleDB extension
+-- Use this instead of views.sql. The logger refreshes the views after every fetch.
+
+-- Create a materialized view for the daily averages in day-ahead prices
+CREATE MATERIALIZED VIEW average_kwh_price_day_by_day AS
+SELECT
+    date_trunc('day', time AT TIME ZONE 'Europe/Helsinki') AT TIME ZONE 'Europe/Helsinki' AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM
+    day_ahead_prices
+WHERE
+    in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY
+    date
+ORDER BY
+    date;
+
+-- Create a materialized view for the monthly averages in day-ahead prices
+CREATE MATERIALIZED VIEW average_kwh_price_month_by_month AS
+SELECT
+    date_trunc('month', time AT TIME ZONE 'Europe/Helsinki') AT TIME ZONE 'Europe/Helsinki' AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM
+    day_ahead_prices
+WHERE
+    in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY
+    date
+ORDER BY
+    date;
+
+-- Create a materialized view for the yearly averages in day-ahead prices
+CREATE MATERIALIZED VIEW average_kwh_price_year_by_year AS
+SELECT
+    date_trunc('year', time AT TIME ZONE 'Europe/Helsinki') AT TIME ZONE 'Europe/Helsinki' AS date,
+    AVG(price / 10) AS avg_price,
+    AVG(price / 10 * (tax_percentage / 100 + 1)) AS avg_price_with_tax,
+    AVG((price / 10 + margin + electricity_tax + transfer_fee) * (tax_percentage / 100 + 1)) AS avg_consumer_price
+FROM
+    day_ahead_prices
+WHERE
+    in_domain = '10YFI-1--------U' AND out_domain = '10YFI-1--------U'
+GROUP BY
+    date
+ORDER BY
+    date;
+
+-- To drop the views, run:
+-- DROP MATERIALIZED VIEW average_kwh_price_day_by_day;
+-- DROP MATERIALIZED VIEW average_kwh_price_month_by_month;
+-- DROP MATERIALIZED VIEW average_kwh_price_year_by_year;
+
+-- Manual refresh for the views
+-- REFRESH MATERIALIZED VIEW average_kwh_price_day_by_day;
+-- REFRESH MATERIALIZED VIEW average_kwh_price_month_by_month;
+-- REFRESH MATERIALIZED VIEW average_kwh_price_year_by_year;